@@ -0,0 +1,263 @@
+use super::{Capability, ThresholdBackend};
+use crate::thresholds::{
+    get_path_for_kind, read_threshold, write_threshold, ThresholdKind, Thresholds,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The kernel's standard `charge_control_{start,end}_threshold` battery attributes.
+pub struct Generic;
+
+impl Generic {
+    pub fn is_supported(battery_path: &Path) -> bool {
+        get_path_for_kind(battery_path, &ThresholdKind::End).exists()
+    }
+}
+
+impl ThresholdBackend for Generic {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn capability(&self, battery_path: &Path) -> Capability {
+        if get_path_for_kind(battery_path, &ThresholdKind::Start).exists() {
+            Capability::StartAndEnd
+        } else {
+            Capability::EndOnly
+        }
+    }
+
+    fn load(&self, battery_path: &Path) -> io::Result<Thresholds> {
+        let start_path = get_path_for_kind(battery_path, &ThresholdKind::Start);
+        let end_path = get_path_for_kind(battery_path, &ThresholdKind::End);
+
+        let start = match read_threshold(&start_path) {
+            Ok(value) => value,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let end = read_threshold(&end_path)?;
+
+        Ok(Thresholds { start, end })
+    }
+
+    /// Firmware rejects a start above the current end (and an end below the current start),
+    /// so the write order depends on what the battery holds right now. See [`write_pair`].
+    fn save(&self, battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        let start_path = get_path_for_kind(battery_path, &ThresholdKind::Start);
+        let end_path = get_path_for_kind(battery_path, &ThresholdKind::End);
+
+        if !start_path.exists() {
+            return write_threshold(&end_path, thresholds.end);
+        }
+
+        let current = self.load(battery_path)?;
+
+        write_pair(&current, thresholds, |kind, value| {
+            write_threshold(&get_path_for_kind(battery_path, &kind), value)
+        })
+    }
+
+    fn files(&self, battery_path: &Path) -> Vec<PathBuf> {
+        [ThresholdKind::Start, ThresholdKind::End]
+            .iter()
+            .map(|kind| get_path_for_kind(battery_path, kind))
+            .filter(|path| path.exists())
+            .collect()
+    }
+}
+
+/// Writes both thresholds in an order the firmware accepts given the `current` pair. If the
+/// second write fails, the first one is rolled back so the battery isn't left half-updated.
+fn write_pair(
+    current: &Thresholds,
+    thresholds: &Thresholds,
+    mut write: impl FnMut(ThresholdKind, u8) -> io::Result<()>,
+) -> io::Result<()> {
+    let (first, second) = if thresholds.start >= current.end {
+        (ThresholdKind::End, ThresholdKind::Start)
+    } else {
+        (ThresholdKind::Start, ThresholdKind::End)
+    };
+
+    write(first, thresholds.get(first))?;
+
+    if let Err(err) = write(second, thresholds.get(second)) {
+        return match write(first, current.get(first)) {
+            Ok(_) => Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "failed to write {} threshold, {} threshold restored to {}%: {}",
+                    second,
+                    first,
+                    current.get(first),
+                    err
+                ),
+            )),
+            Err(rollback_err) => Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "failed to write {} threshold ({}) and to restore {} threshold ({})",
+                    second, err, first, rollback_err
+                ),
+            )),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Stands in for firmware that rejects a start at or above the end and vice versa.
+    struct Firmware {
+        thresholds: Thresholds,
+        /// Indices of the writes that fail.
+        failing: Vec<usize>,
+        writes: Vec<(ThresholdKind, u8)>,
+    }
+
+    impl Firmware {
+        fn new(start: u8, end: u8) -> Self {
+            Self {
+                thresholds: Thresholds { start, end },
+                failing: Vec::new(),
+                writes: Vec::new(),
+            }
+        }
+
+        fn write(&mut self, kind: ThresholdKind, value: u8) -> io::Result<()> {
+            let index = self.writes.len();
+            self.writes.push((kind, value));
+
+            let valid = match kind {
+                ThresholdKind::Start => value < self.thresholds.end,
+                ThresholdKind::End => value > self.thresholds.start,
+            };
+            if self.failing.contains(&index) || !valid {
+                return Err(io::Error::from_raw_os_error(22));
+            }
+
+            match kind {
+                ThresholdKind::Start => self.thresholds.start = value,
+                ThresholdKind::End => self.thresholds.end = value,
+            }
+            Ok(())
+        }
+
+        fn save(&mut self, start: u8, end: u8) -> io::Result<()> {
+            let current = self.thresholds.clone();
+            write_pair(&current, &Thresholds { start, end }, |kind, value| {
+                self.write(kind, value)
+            })
+        }
+    }
+
+    fn battery(start: u8, end: u8) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("charge_control_start_threshold"),
+            start.to_string(),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("charge_control_end_threshold"),
+            end.to_string(),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn raises_start_above_current_end() {
+        let mut firmware = Firmware::new(40, 80);
+        firmware.save(85, 95).unwrap();
+
+        assert_eq!(firmware.thresholds, Thresholds { start: 85, end: 95 });
+        assert_eq!(
+            firmware.writes,
+            [(ThresholdKind::End, 95), (ThresholdKind::Start, 85)]
+        );
+    }
+
+    #[test]
+    fn lowers_end_below_current_start() {
+        let mut firmware = Firmware::new(85, 95);
+        firmware.save(40, 80).unwrap();
+
+        assert_eq!(firmware.thresholds, Thresholds { start: 40, end: 80 });
+        assert_eq!(
+            firmware.writes,
+            [(ThresholdKind::Start, 40), (ThresholdKind::End, 80)]
+        );
+    }
+
+    #[test]
+    fn rolls_back_first_write_when_second_fails() {
+        let mut firmware = Firmware::new(40, 80);
+        firmware.failing = vec![1];
+
+        let err = firmware.save(85, 95).unwrap_err();
+
+        assert_eq!(firmware.thresholds, Thresholds { start: 40, end: 80 });
+        assert_eq!(
+            firmware.writes.last(),
+            Some(&(ThresholdKind::End, 80)),
+            "end threshold should be restored"
+        );
+        assert!(err.to_string().contains("end threshold restored to 80%"));
+    }
+
+    #[test]
+    fn reports_failed_rollback() {
+        let mut firmware = Firmware::new(40, 80);
+        firmware.failing = vec![1, 2];
+
+        let err = firmware.save(20, 60).unwrap_err();
+
+        assert_eq!(firmware.thresholds, Thresholds { start: 20, end: 80 });
+        assert_eq!(firmware.writes.len(), 3);
+        assert!(err.to_string().contains("and to restore start threshold"));
+    }
+
+    #[test]
+    fn saves_to_sysfs() {
+        let dir = battery(40, 80);
+
+        Generic
+            .save(dir.path(), &Thresholds { start: 85, end: 95 })
+            .unwrap();
+        assert_eq!(
+            Generic.load(dir.path()).unwrap(),
+            Thresholds { start: 85, end: 95 }
+        );
+
+        Generic
+            .save(dir.path(), &Thresholds { start: 20, end: 50 })
+            .unwrap();
+        assert_eq!(
+            Generic.load(dir.path()).unwrap(),
+            Thresholds { start: 20, end: 50 }
+        );
+    }
+
+    #[test]
+    fn end_only_battery() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("charge_control_end_threshold"), "100").unwrap();
+
+        assert_eq!(Generic.capability(dir.path()), Capability::EndOnly);
+        Generic
+            .save(dir.path(), &Thresholds { start: 40, end: 80 })
+            .unwrap();
+        assert_eq!(
+            Generic.load(dir.path()).unwrap(),
+            Thresholds { start: 0, end: 80 }
+        );
+    }
+}
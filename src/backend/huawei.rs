@@ -0,0 +1,112 @@
+use super::{Capability, ThresholdBackend};
+use crate::thresholds::Thresholds;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Huawei MateBook `huawei-wmi`, which takes both thresholds as "<start> <end>" in one file.
+pub struct Huawei {
+    path: PathBuf,
+}
+
+impl Huawei {
+    pub fn new(sysfs_root: &Path) -> Self {
+        Self {
+            path: sysfs_root.join("devices/platform/huawei-wmi/charge_control_thresholds"),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+impl ThresholdBackend for Huawei {
+    fn name(&self) -> &'static str {
+        "huawei"
+    }
+
+    fn capability(&self, _battery_path: &Path) -> Capability {
+        Capability::StartAndEnd
+    }
+
+    fn load(&self, _battery_path: &Path) -> io::Result<Thresholds> {
+        let contents = fs::read_to_string(&self.path)?;
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid threshold value: {}", contents.trim()),
+            )
+        };
+
+        let mut values = contents.split_whitespace().map(|v| v.parse::<u8>());
+        let start = values.next().and_then(Result::ok).ok_or_else(invalid)?;
+        let end = values.next().and_then(Result::ok).ok_or_else(invalid)?;
+
+        // "0 0" and "0 100" both mean thresholds are disabled
+        let end = if end == 0 { 100 } else { end };
+
+        Ok(Thresholds { start, end })
+    }
+
+    fn save(&self, _battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        fs::write(
+            &self.path,
+            format!("{} {}", thresholds.start, thresholds.end),
+        )
+    }
+
+    fn files(&self, _battery_path: &Path) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(contents: &str) -> (tempfile::TempDir, Huawei) {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("devices/platform/huawei-wmi");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("charge_control_thresholds"), contents).unwrap();
+
+        let backend = Huawei::new(root.path());
+        (root, backend)
+    }
+
+    #[test]
+    fn loads_both_thresholds_from_one_file() {
+        let (_root, backend) = setup("40 80\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 40, end: 80 }
+        );
+    }
+
+    #[test]
+    fn zero_end_means_disabled() {
+        let (_root, backend) = setup("0 0\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 100 }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_contents() {
+        let (_root, backend) = setup("40\n");
+        let err = backend.load(Path::new("")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn saves_both_thresholds() {
+        let (_root, backend) = setup("0 0");
+        backend
+            .save(Path::new(""), &Thresholds { start: 55, end: 75 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "55 75");
+    }
+}
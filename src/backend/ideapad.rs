@@ -0,0 +1,115 @@
+use super::{Capability, ThresholdBackend};
+use crate::thresholds::{read_threshold, write_threshold, Thresholds};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Lenovo IdeaPad `conservation_mode`, which holds the battery at roughly 60% when enabled.
+pub struct Ideapad {
+    driver_path: PathBuf,
+}
+
+const CONSERVATION_LEVEL: u8 = 60;
+
+impl Ideapad {
+    pub fn new(sysfs_root: &Path) -> Self {
+        Self {
+            driver_path: sysfs_root.join("bus/platform/drivers/ideapad_acpi"),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.mode_path().is_ok()
+    }
+
+    /// The device directory is named after its ACPI id (usually `VPC2004:00`), so look it up.
+    fn mode_path(&self) -> io::Result<PathBuf> {
+        fs::read_dir(&self.driver_path)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path().join("conservation_mode"))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "conservation_mode not found in {}",
+                        self.driver_path.display()
+                    ),
+                )
+            })
+    }
+}
+
+impl ThresholdBackend for Ideapad {
+    fn name(&self) -> &'static str {
+        "ideapad"
+    }
+
+    fn capability(&self, _battery_path: &Path) -> Capability {
+        Capability::OnOff
+    }
+
+    fn load(&self, _battery_path: &Path) -> io::Result<Thresholds> {
+        let enabled = read_threshold(&self.mode_path()?)? != 0;
+        let end = if enabled { CONSERVATION_LEVEL } else { 100 };
+
+        Ok(Thresholds { start: 0, end })
+    }
+
+    fn save(&self, _battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        let enabled = thresholds.end < 100;
+        write_threshold(&self.mode_path()?, enabled as u8)
+    }
+
+    fn files(&self, _battery_path: &Path) -> Vec<PathBuf> {
+        self.mode_path().into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(mode: &str) -> (tempfile::TempDir, Ideapad, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let device = root
+            .path()
+            .join("bus/platform/drivers/ideapad_acpi/VPC2004:00");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("conservation_mode"), mode).unwrap();
+
+        let backend = Ideapad::new(root.path());
+        (root, backend, device.join("conservation_mode"))
+    }
+
+    #[test]
+    fn conservation_mode_maps_to_end_level() {
+        let (_root, backend, _) = setup("1\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 60 }
+        );
+
+        let (_root, backend, _) = setup("0\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 100 }
+        );
+    }
+
+    #[test]
+    fn any_end_below_100_enables_conservation_mode() {
+        let (_root, backend, mode) = setup("0");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 40, end: 80 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&mode).unwrap(), "1");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 100 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&mode).unwrap(), "0");
+    }
+}
@@ -0,0 +1,232 @@
+mod generic;
+mod huawei;
+mod ideapad;
+mod msi;
+mod samsung;
+mod sony;
+
+use crate::thresholds::Thresholds;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+pub use generic::Generic;
+pub use huawei::Huawei;
+pub use ideapad::Ideapad;
+pub use msi::Msi;
+pub use samsung::Samsung;
+pub use sony::Sony;
+
+/// Which thresholds a backend can actually control.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    StartAndEnd,
+    EndOnly,
+    /// A single on/off switch that caps the charge at a fixed level when enabled.
+    OnOff,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StartAndEnd => write!(f, "start and end"),
+            Self::EndOnly => write!(f, "end only"),
+            Self::OnOff => write!(f, "on/off only"),
+        }
+    }
+}
+
+/// A driver-specific way of reading and writing charge thresholds.
+pub trait ThresholdBackend {
+    fn name(&self) -> &'static str;
+
+    fn capability(&self, battery_path: &Path) -> Capability;
+
+    fn load(&self, battery_path: &Path) -> io::Result<Thresholds>;
+
+    fn save(&self, battery_path: &Path, thresholds: &Thresholds) -> io::Result<()>;
+
+    /// sysfs files written by `save`, used to grant unprivileged write access.
+    fn files(&self, battery_path: &Path) -> Vec<PathBuf>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    Generic,
+    Ideapad,
+    Huawei,
+    Samsung,
+    Sony,
+    Msi,
+}
+
+/// Picks the backend for each battery, either forced with `--backend` or detected from
+/// the DMI vendor and the files present in sysfs.
+pub struct Backends {
+    sysfs_root: PathBuf,
+    forced: Option<BackendKind>,
+}
+
+impl Backends {
+    pub fn new(power_supply_path: &Path, forced: Option<BackendKind>) -> Self {
+        // power_supply lives at <sysfs>/class/power_supply
+        let sysfs_root = power_supply_path
+            .ancestors()
+            .nth(2)
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("/sys"))
+            .to_path_buf();
+
+        Self { sysfs_root, forced }
+    }
+
+    pub fn for_battery(&self, battery_path: &Path) -> Box<dyn ThresholdBackend> {
+        match self.forced {
+            Some(kind) => self.create(kind),
+            None => self.create(self.detect(battery_path)),
+        }
+    }
+
+    fn create(&self, kind: BackendKind) -> Box<dyn ThresholdBackend> {
+        let root = &self.sysfs_root;
+        match kind {
+            BackendKind::Generic => Box::new(Generic),
+            BackendKind::Ideapad => Box::new(Ideapad::new(root)),
+            BackendKind::Huawei => Box::new(Huawei::new(root)),
+            BackendKind::Samsung => Box::new(Samsung::new(root)),
+            BackendKind::Sony => Box::new(Sony::new(root)),
+            BackendKind::Msi => Box::new(Msi),
+        }
+    }
+
+    fn detect(&self, battery_path: &Path) -> BackendKind {
+        let root = &self.sysfs_root;
+        let vendor = fs::read_to_string(root.join("class/dmi/id/sys_vendor"))
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if Generic::is_supported(battery_path) {
+            if vendor.contains("micro-star") {
+                return BackendKind::Msi;
+            }
+            return BackendKind::Generic;
+        }
+
+        if Ideapad::new(root).is_supported() {
+            BackendKind::Ideapad
+        } else if Huawei::new(root).is_supported() {
+            BackendKind::Huawei
+        } else if Samsung::new(root).is_supported() {
+            BackendKind::Samsung
+        } else if Sony::new(root).is_supported() {
+            BackendKind::Sony
+        } else {
+            BackendKind::Generic
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sysfs {
+        root: tempfile::TempDir,
+    }
+
+    impl Sysfs {
+        fn new() -> Self {
+            let sysfs = Self {
+                root: tempfile::tempdir().unwrap(),
+            };
+            fs::create_dir_all(sysfs.battery()).unwrap();
+            sysfs
+        }
+
+        fn power_supply(&self) -> PathBuf {
+            self.root.path().join("class/power_supply")
+        }
+
+        fn battery(&self) -> PathBuf {
+            self.power_supply().join("BAT0")
+        }
+
+        fn write(&self, path: &str, contents: &str) {
+            let path = self.root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        fn detect(&self) -> BackendKind {
+            Backends::new(&self.power_supply(), None).detect(&self.battery())
+        }
+    }
+
+    #[test]
+    fn detects_generic_from_end_threshold() {
+        let sysfs = Sysfs::new();
+        sysfs.write("class/power_supply/BAT0/charge_control_end_threshold", "80");
+        sysfs.write("class/dmi/id/sys_vendor", "LENOVO\n");
+
+        assert_eq!(sysfs.detect(), BackendKind::Generic);
+    }
+
+    #[test]
+    fn detects_msi_from_dmi_vendor() {
+        let sysfs = Sysfs::new();
+        sysfs.write("class/power_supply/BAT0/charge_control_end_threshold", "80");
+        sysfs.write(
+            "class/dmi/id/sys_vendor",
+            "Micro-Star International Co., Ltd.\n",
+        );
+
+        assert_eq!(sysfs.detect(), BackendKind::Msi);
+    }
+
+    #[test]
+    fn detects_vendor_drivers() {
+        let cases = [
+            (
+                "bus/platform/drivers/ideapad_acpi/VPC2004:00/conservation_mode",
+                BackendKind::Ideapad,
+            ),
+            (
+                "devices/platform/huawei-wmi/charge_control_thresholds",
+                BackendKind::Huawei,
+            ),
+            (
+                "devices/platform/samsung/battery_life_extender",
+                BackendKind::Samsung,
+            ),
+            (
+                "devices/platform/sony-laptop/battery_care_limiter",
+                BackendKind::Sony,
+            ),
+        ];
+
+        for (path, kind) in cases {
+            let sysfs = Sysfs::new();
+            sysfs.write(path, "0");
+            assert_eq!(sysfs.detect(), kind, "{}", path);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_generic() {
+        assert_eq!(Sysfs::new().detect(), BackendKind::Generic);
+    }
+
+    #[test]
+    fn forced_backend_skips_detection() {
+        let sysfs = Sysfs::new();
+        sysfs.write("class/power_supply/BAT0/charge_control_end_threshold", "80");
+
+        let backends = Backends::new(&sysfs.power_supply(), Some(BackendKind::Sony));
+        assert_eq!(backends.for_battery(&sysfs.battery()).name(), "sony");
+    }
+}
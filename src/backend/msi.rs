@@ -0,0 +1,62 @@
+use super::{Capability, Generic, ThresholdBackend};
+use crate::thresholds::{get_path_for_kind, write_threshold, ThresholdKind, Thresholds};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// MSI laptops driven by `msi-ec`. Only the end threshold is writable; the EC resumes
+/// charging 10% below it.
+pub struct Msi;
+
+const START_OFFSET: u8 = 10;
+
+impl ThresholdBackend for Msi {
+    fn name(&self) -> &'static str {
+        "msi"
+    }
+
+    fn capability(&self, _battery_path: &Path) -> Capability {
+        Capability::EndOnly
+    }
+
+    fn load(&self, battery_path: &Path) -> io::Result<Thresholds> {
+        let mut thresholds = Generic.load(battery_path)?;
+        if !get_path_for_kind(battery_path, &ThresholdKind::Start).exists() {
+            thresholds.start = thresholds.end.saturating_sub(START_OFFSET);
+        }
+        Ok(thresholds)
+    }
+
+    fn save(&self, battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        let end_path = get_path_for_kind(battery_path, &ThresholdKind::End);
+        write_threshold(&end_path, thresholds.end)
+    }
+
+    fn files(&self, battery_path: &Path) -> Vec<PathBuf> {
+        vec![get_path_for_kind(battery_path, &ThresholdKind::End)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn start_follows_end_and_only_end_is_written() {
+        let battery = tempfile::tempdir().unwrap();
+        let end_path = get_path_for_kind(battery.path(), &ThresholdKind::End);
+        fs::write(&end_path, "80\n").unwrap();
+
+        assert_eq!(
+            Msi.load(battery.path()).unwrap(),
+            Thresholds { start: 70, end: 80 }
+        );
+
+        Msi.save(battery.path(), &Thresholds { start: 20, end: 60 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&end_path).unwrap(), "60");
+        assert!(!get_path_for_kind(battery.path(), &ThresholdKind::Start).exists());
+    }
+}
@@ -0,0 +1,97 @@
+use super::{Capability, ThresholdBackend};
+use crate::thresholds::{read_threshold, write_threshold, Thresholds};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Samsung `battery_life_extender`, which stops charging at 80% when enabled.
+pub struct Samsung {
+    path: PathBuf,
+}
+
+const EXTENDER_LEVEL: u8 = 80;
+
+impl Samsung {
+    pub fn new(sysfs_root: &Path) -> Self {
+        Self {
+            path: sysfs_root.join("devices/platform/samsung/battery_life_extender"),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+impl ThresholdBackend for Samsung {
+    fn name(&self) -> &'static str {
+        "samsung"
+    }
+
+    fn capability(&self, _battery_path: &Path) -> Capability {
+        Capability::OnOff
+    }
+
+    fn load(&self, _battery_path: &Path) -> io::Result<Thresholds> {
+        let enabled = read_threshold(&self.path)? != 0;
+        let end = if enabled { EXTENDER_LEVEL } else { 100 };
+
+        Ok(Thresholds { start: 0, end })
+    }
+
+    fn save(&self, _battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        let enabled = thresholds.end < 100;
+        write_threshold(&self.path, enabled as u8)
+    }
+
+    fn files(&self, _battery_path: &Path) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn setup(contents: &str) -> (tempfile::TempDir, Samsung) {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("devices/platform/samsung");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("battery_life_extender"), contents).unwrap();
+
+        let backend = Samsung::new(root.path());
+        (root, backend)
+    }
+
+    #[test]
+    fn extender_maps_to_end_level() {
+        let (_root, backend) = setup("1\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 80 }
+        );
+
+        let (_root, backend) = setup("0\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 100 }
+        );
+    }
+
+    #[test]
+    fn any_end_below_100_enables_extender() {
+        let (_root, backend) = setup("0");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 90 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "1");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 100 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "0");
+    }
+}
@@ -0,0 +1,122 @@
+use super::{Capability, ThresholdBackend};
+use crate::thresholds::{read_threshold, write_threshold, Thresholds};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Sony VAIO `battery_care_limiter`, which accepts a fixed set of end levels.
+pub struct Sony {
+    path: PathBuf,
+}
+
+const LEVELS: [u8; 3] = [50, 80, 100];
+
+impl Sony {
+    pub fn new(sysfs_root: &Path) -> Self {
+        Self {
+            path: sysfs_root.join("devices/platform/sony-laptop/battery_care_limiter"),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+impl ThresholdBackend for Sony {
+    fn name(&self) -> &'static str {
+        "sony"
+    }
+
+    fn capability(&self, _battery_path: &Path) -> Capability {
+        Capability::EndOnly
+    }
+
+    fn load(&self, _battery_path: &Path) -> io::Result<Thresholds> {
+        // 0 means the limiter is disabled
+        let end = match read_threshold(&self.path)? {
+            0 => 100,
+            value => value,
+        };
+
+        Ok(Thresholds { start: 0, end })
+    }
+
+    fn save(&self, _battery_path: &Path, thresholds: &Thresholds) -> io::Result<()> {
+        if !LEVELS.contains(&thresholds.end) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sony battery care limiter only supports end thresholds of 50, 80 or 100",
+            ));
+        }
+
+        let value = if thresholds.end == 100 {
+            0
+        } else {
+            thresholds.end
+        };
+        write_threshold(&self.path, value)
+    }
+
+    fn files(&self, _battery_path: &Path) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn setup(contents: &str) -> (tempfile::TempDir, Sony) {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("devices/platform/sony-laptop");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("battery_care_limiter"), contents).unwrap();
+
+        let backend = Sony::new(root.path());
+        (root, backend)
+    }
+
+    #[test]
+    fn zero_means_disabled() {
+        let (_root, backend) = setup("0\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 100 }
+        );
+
+        let (_root, backend) = setup("80\n");
+        assert_eq!(
+            backend.load(Path::new("")).unwrap(),
+            Thresholds { start: 0, end: 80 }
+        );
+    }
+
+    #[test]
+    fn saves_supported_levels() {
+        let (_root, backend) = setup("0");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 50 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "50");
+
+        backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 100 })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "0");
+    }
+
+    #[test]
+    fn rejects_unsupported_levels() {
+        let (_root, backend) = setup("80");
+
+        let err = backend
+            .save(Path::new(""), &Thresholds { start: 0, end: 70 })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&backend.path).unwrap(), "80");
+    }
+}
@@ -1,32 +1,247 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt, fs, io,
     path::{Path, PathBuf},
     str::FromStr,
+    time::{Duration, Instant},
 };
 
-#[derive(Clone)]
+/// Weight of the newest reading in the smoothed charge or discharge rate.
+const RATE_SMOOTHING: f32 = 0.2;
+
+/// The kernel's `status` attribute.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BatteryStatus {
     Charging,
+    Discharging,
+    /// Plugged in but held below full, usually by the end threshold.
+    #[serde(rename = "not charging")]
     NotCharging,
+    Full,
     Unknown,
 }
 
 impl BatteryStatus {
+    /// Parses a kernel status string, or one produced by [`BatteryStatus::as_str`].
+    pub fn parse(status: &str) -> Option<Self> {
+        match status.trim().to_lowercase().as_str() {
+            "charging" => Some(Self::Charging),
+            "discharging" => Some(Self::Discharging),
+            "not charging" => Some(Self::NotCharging),
+            "full" => Some(Self::Full),
+            "unknown" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::Charging => "charging",
+            Self::Discharging => "discharging",
             Self::NotCharging => "not charging",
+            Self::Full => "full",
             Self::Unknown => "unknown",
         }
     }
 }
 
+/// The kernel's `charge_type` attribute: how the charger is currently charging.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChargeType {
+    Trickle,
+    Fast,
+    Standard,
+    Adaptive,
+    Custom,
+    LongLife,
+    Bypass,
+}
+
+impl ChargeType {
+    /// Parses a kernel charge type. "Unknown" and "N/A" yield `None`.
+    pub fn parse(charge_type: &str) -> Option<Self> {
+        match charge_type.trim().to_lowercase().as_str() {
+            "trickle" => Some(Self::Trickle),
+            "fast" => Some(Self::Fast),
+            "standard" => Some(Self::Standard),
+            "adaptive" => Some(Self::Adaptive),
+            "custom" => Some(Self::Custom),
+            "long life" => Some(Self::LongLife),
+            "bypass" => Some(Self::Bypass),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trickle => "trickle",
+            Self::Fast => "fast",
+            Self::Standard => "standard",
+            Self::Adaptive => "adaptive",
+            Self::Custom => "custom",
+            Self::LongLife => "long life",
+            Self::Bypass => "bypass",
+        }
+    }
+}
+
+/// The kernel's `charge_behaviour` attribute: whether charging is inhibited or forced
+/// regardless of the thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChargeBehaviour {
+    Auto,
+    InhibitCharge,
+    InhibitChargeAwake,
+    ForceDischarge,
+}
+
+impl ChargeBehaviour {
+    /// Parses the active behaviour from a list like `[auto] inhibit-charge force-discharge`,
+    /// or from a single value.
+    pub fn parse(behaviour: &str) -> Option<Self> {
+        let active = behaviour
+            .split_whitespace()
+            .find_map(|word| word.strip_prefix('[')?.strip_suffix(']'))
+            .unwrap_or_else(|| behaviour.trim());
+
+        match active {
+            "auto" => Some(Self::Auto),
+            "inhibit-charge" => Some(Self::InhibitCharge),
+            "inhibit-charge-awake" => Some(Self::InhibitChargeAwake),
+            "force-discharge" => Some(Self::ForceDischarge),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::InhibitCharge => "inhibit-charge",
+            Self::InhibitChargeAwake => "inhibit-charge-awake",
+            Self::ForceDischarge => "force-discharge",
+        }
+    }
+
+    /// The active behaviour of the battery at `path`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let behaviour = read_str_battery_attribute(path, BatteryAttribute::ChargeBehaviour)?;
+        Self::parse(&behaviour).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown charge behaviour: {}", behaviour.trim()),
+            )
+        })
+    }
+
+    /// Every behaviour the battery at `path` accepts, in the order the kernel lists them.
+    /// Empty when the battery has no `charge_behaviour` attribute.
+    pub fn available(path: &Path) -> Vec<Self> {
+        read_str_battery_attribute(path, BatteryAttribute::ChargeBehaviour)
+            .map(|behaviours| {
+                behaviours
+                    .split_whitespace()
+                    .filter_map(|word| Self::parse(word.trim_matches(['[', ']'])))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The `charge_behaviour` attribute of the battery at `path`, if it has one.
+    pub fn file(path: &Path) -> Option<PathBuf> {
+        Some(path.join(BatteryAttribute::ChargeBehaviour.file_name())).filter(|file| file.exists())
+    }
+
+    /// Writes the behaviour and reads it back, refusing values the battery doesn't list.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let available = Self::available(path);
+        if available.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "battery has no charge_behaviour attribute",
+            ));
+        }
+        if !available.contains(self) {
+            let names: Vec<&str> = available.iter().map(Self::as_str).collect();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "charge behaviour {} is not supported (available: {})",
+                    self.as_str(),
+                    names.join(", ")
+                ),
+            ));
+        }
+
+        fs::write(
+            path.join(BatteryAttribute::ChargeBehaviour.file_name()),
+            self.as_str(),
+        )?;
+
+        let accepted = Self::load(path)?;
+        if accepted != *self {
+            return Err(io::Error::other(format!(
+                "firmware kept charge behaviour {} instead of {}",
+                accepted.as_str(),
+                self.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Unit of the capacity values stored in a [`Battery`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapacityUnit {
+    /// µWh, read from `energy_*` or converted from `charge_*` using the voltage.
+    Energy,
+    /// µAh, read from `charge_*` when no voltage is available for conversion.
+    Charge,
+    /// Percent of full, read from `capacity` on peripherals that report nothing else.
+    Percent,
+}
+
+impl CapacityUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Energy => "µWh",
+            Self::Charge => "µAh",
+            Self::Percent => "%",
+        }
+    }
+
+    pub fn parse(unit: &str) -> Option<Self> {
+        [Self::Energy, Self::Charge, Self::Percent]
+            .into_iter()
+            .find(|candidate| candidate.as_str() == unit)
+    }
+}
+
 pub enum BatteryAttribute {
     CurrPower,
     TotalPower,
     Status,
     Cycles,
     DesignPower,
+    CurrCharge,
+    TotalCharge,
+    DesignCharge,
+    Voltage,
+    VoltageMinDesign,
+    PowerNow,
+    CurrentNow,
+    ChargeType,
+    ChargeBehaviour,
+    Temp,
+    Capacity,
+    CapacityLevel,
+    Type,
+    Scope,
+    ModelName,
+    Manufacturer,
 }
 
 impl BatteryAttribute {
@@ -37,6 +252,22 @@ impl BatteryAttribute {
             Self::Status => "status",
             Self::Cycles => "cycle_count",
             Self::DesignPower => "energy_full_design",
+            Self::CurrCharge => "charge_now",
+            Self::TotalCharge => "charge_full",
+            Self::DesignCharge => "charge_full_design",
+            Self::Voltage => "voltage_now",
+            Self::VoltageMinDesign => "voltage_min_design",
+            Self::PowerNow => "power_now",
+            Self::CurrentNow => "current_now",
+            Self::ChargeType => "charge_type",
+            Self::ChargeBehaviour => "charge_behaviour",
+            Self::Temp => "temp",
+            Self::Capacity => "capacity",
+            Self::CapacityLevel => "capacity_level",
+            Self::Type => "type",
+            Self::Scope => "scope",
+            Self::ModelName => "model_name",
+            Self::Manufacturer => "manufacturer",
         }
     }
 }
@@ -49,60 +280,124 @@ impl fmt::Display for BatteryAttribute {
             Self::Status => write!(f, "status"),
             Self::Cycles => write!(f, "cycle count"),
             Self::DesignPower => write!(f, "design power"),
+            Self::CurrCharge => write!(f, "current charge"),
+            Self::TotalCharge => write!(f, "total charge"),
+            Self::DesignCharge => write!(f, "design charge"),
+            Self::Voltage => write!(f, "voltage"),
+            Self::VoltageMinDesign => write!(f, "design voltage"),
+            Self::PowerNow => write!(f, "power draw"),
+            Self::CurrentNow => write!(f, "current"),
+            Self::ChargeType => write!(f, "charge type"),
+            Self::ChargeBehaviour => write!(f, "charge behaviour"),
+            Self::Temp => write!(f, "temperature"),
+            Self::Capacity => write!(f, "capacity"),
+            Self::CapacityLevel => write!(f, "capacity level"),
+            Self::Type => write!(f, "type"),
+            Self::Scope => write!(f, "scope"),
+            Self::ModelName => write!(f, "model name"),
+            Self::Manufacturer => write!(f, "manufacturer"),
+        }
+    }
+}
+
+/// Electrical readings of a [`Battery`], converted from the µW, µA, µV and deci-°C
+/// reported by sysfs. Each is `None` when the driver doesn't expose it.
+#[derive(Clone, Copy, Default)]
+pub struct PowerReadings {
+    /// Watts, from `power_now` or derived from `current_now` and `voltage_now`.
+    pub power: Option<f32>,
+    /// Amperes.
+    pub current: Option<f32>,
+    /// Volts.
+    pub voltage: Option<f32>,
+    /// Nominal minimum voltage in volts.
+    pub voltage_min_design: Option<f32>,
+    /// Degrees Celsius.
+    pub temperature: Option<f32>,
+}
+
+impl PowerReadings {
+    fn read(path: &Path, power_draw: Option<u32>) -> Self {
+        let volts = |attr| {
+            read_num_battery_attribute::<u32>(path, attr)
+                .ok()
+                .map(|uv| uv as f32 / 1_000_000.0)
+        };
+
+        Self {
+            power: power_draw.map(|uw| uw as f32 / 1_000_000.0),
+            current: read_current(path).map(|ua| ua as f32 / 1_000_000.0),
+            voltage: volts(BatteryAttribute::Voltage),
+            voltage_min_design: volts(BatteryAttribute::VoltageMinDesign),
+            temperature: read_num_battery_attribute::<i32>(path, BatteryAttribute::Temp)
+                .ok()
+                .map(|deci| deci as f32 / 10.0),
         }
     }
 }
 
 pub struct Battery {
     path: PathBuf,
+    pub scope: BatteryScope,
     pub total_power: u32,
     pub curr_power: u32,
+    pub design_power: Option<u32>,
+    pub unit: CapacityUnit,
     pub status: BatteryStatus,
-    pub cycles: Option<u8>,
+    /// `None` when the driver doesn't report a charge type or reports it as unknown.
+    pub charge_type: Option<ChargeType>,
+    /// `None` when the driver doesn't support `charge_behaviour`.
+    pub charge_behaviour: Option<ChargeBehaviour>,
+    pub cycles: Option<u32>,
     pub battery_health: Option<f32>,
+    /// Instantaneous charge or discharge rate in µW.
+    pub power_draw: Option<u32>,
+    pub readings: PowerReadings,
+    /// Smoothed charge or discharge rate in [`CapacityUnit`] per hour (µW or µA).
+    rate: Option<f32>,
+    /// Capacity reading and when it last changed, for deriving the rate from
+    /// `energy_now` deltas when the driver reports no power or current.
+    last_change: Option<(u32, Instant)>,
 }
 
 impl Battery {
     pub fn new(path: &Path) -> io::Result<(Self, Vec<String>)> {
         let mut warnings = Vec::new();
-        let battery_name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
-
-        let curr_power: u32 = read_num_battery_attribute(path, BatteryAttribute::CurrPower)
-            .map_err(|e| {
-                io::Error::new(
-                    e.kind(),
-                    format!(
-                        "Failed to read {} for {}: {}",
-                        BatteryAttribute::CurrPower,
-                        battery_name,
-                        e
-                    ),
-                )
-            })?;
-
-        let total_power: u32 = read_num_battery_attribute(path, BatteryAttribute::TotalPower)
-            .map_err(|e| {
-                io::Error::new(
-                    e.kind(),
-                    format!(
-                        "Failed to read {} for {}: {}",
-                        BatteryAttribute::TotalPower,
-                        battery_name,
-                        e
-                    ),
-                )
-            })?;
+        let battery_name = battery_name(path);
 
-        let status = read_str_battery_attribute(path, BatteryAttribute::Status)
-            .map(
-                |status_str| match status_str.trim().to_lowercase().as_str() {
-                    "charging" => BatteryStatus::Charging,
-                    _ => BatteryStatus::NotCharging,
-                },
+        let capacity = read_capacity(path).map_err(|(attr, e)| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read {} for {}: {}", attr, battery_name, e),
             )
+        })?;
+
+        if capacity.unit == CapacityUnit::Charge {
+            warnings.push(format!(
+                "No voltage reported for {}. Capacity shown in {}.",
+                battery_name,
+                capacity.unit.as_str()
+            ));
+        }
+
+        let Capacity {
+            curr: curr_power,
+            total: total_power,
+            design: design_power,
+            unit,
+        } = capacity;
+
+        let status = read_str_battery_attribute(path, BatteryAttribute::Status)
+            .map(|status_str| {
+                BatteryStatus::parse(&status_str).unwrap_or_else(|| {
+                    warnings.push(format!(
+                        "Unrecognised status '{}' for {}. Using 'unknown'.",
+                        status_str.trim(),
+                        battery_name
+                    ));
+                    BatteryStatus::Unknown
+                })
+            })
             .unwrap_or_else(|e| {
                 warnings.push(format!(
                     "Failed to read status for {}: {}. Using 'unknown'.",
@@ -111,13 +406,18 @@ impl Battery {
                 BatteryStatus::Unknown
             });
 
-        let cycles: Option<u8> = read_num_battery_attribute(path, BatteryAttribute::Cycles).ok();
+        let charge_type = read_str_battery_attribute(path, BatteryAttribute::ChargeType)
+            .ok()
+            .and_then(|charge_type| ChargeType::parse(&charge_type));
+        let charge_behaviour = read_str_battery_attribute(path, BatteryAttribute::ChargeBehaviour)
+            .ok()
+            .and_then(|behaviour| ChargeBehaviour::parse(&behaviour));
 
-        let design_power: Option<u32> =
-            read_num_battery_attribute(path, BatteryAttribute::DesignPower).ok();
+        let cycles: Option<u32> = read_num_battery_attribute(path, BatteryAttribute::Cycles).ok();
 
         let battery_health: Option<f32> = match design_power {
             Some(design) if design > 0 => Some((total_power as f32 / design as f32) * 100.0),
+            _ if unit == CapacityUnit::Percent => None,
             _ => {
                 warnings.push(format!(
                     "Failed to read design power for {}. Battery health unavailable.",
@@ -127,21 +427,65 @@ impl Battery {
             }
         };
 
+        let power_draw = read_power_draw(path);
+        let readings = PowerReadings::read(path, power_draw);
+        let rate = match unit {
+            CapacityUnit::Energy => power_draw.map(|p| p as f32),
+            CapacityUnit::Charge => read_current(path).map(|c| c as f32),
+            CapacityUnit::Percent => None,
+        };
+
         Ok((
             Self {
                 path: path.to_path_buf(),
+                scope: battery_scope(path),
                 curr_power,
                 total_power,
+                design_power,
+                unit,
                 status,
+                charge_type,
+                charge_behaviour,
                 cycles,
                 battery_health,
+                power_draw,
+                readings,
+                rate,
+                last_change: None,
             },
             warnings,
         ))
     }
 
+    /// Rereads the battery, smoothing the rate with the previous readings. The rate
+    /// starts over when the status changes, e.g. when the charger is plugged in.
     pub fn refresh(&mut self) -> io::Result<Vec<String>> {
-        let (battery, warnings) = Self::new(&self.path)?;
+        let (mut battery, warnings) = Self::new(&self.path)?;
+
+        if battery.status == self.status && battery.unit == self.unit {
+            let reported = battery.rate.is_some();
+            battery.rate = match battery.rate {
+                Some(rate) => Some(smooth(self.rate, rate)),
+                None => self.rate,
+            };
+
+            if battery.curr_power == self.curr_power {
+                battery.last_change = self.last_change;
+            } else {
+                let now = Instant::now();
+                // The first change only marks a starting point, since it's unknown how
+                // long the previous value had been reported.
+                if let Some((prev, since)) = self.last_change {
+                    let hours = now.duration_since(since).as_secs_f32() / 3600.0;
+                    if !reported && hours > 0.0 {
+                        let delta = battery.curr_power.abs_diff(prev) as f32 / hours;
+                        battery.rate = Some(smooth(self.rate, delta));
+                    }
+                }
+                battery.last_change = Some((battery.curr_power, now));
+            }
+        }
+
         *self = battery;
         Ok(warnings)
     }
@@ -153,23 +497,182 @@ impl Battery {
     pub fn health_percentage(&self) -> Option<f32> {
         self.battery_health
     }
+
+    /// Time until a charging battery reaches the `end` threshold (in percent).
+    pub fn time_to_threshold(&self, end: u8) -> Option<Duration> {
+        if self.status != BatteryStatus::Charging {
+            return None;
+        }
+
+        let target = self.total_power as f32 * end as f32 / 100.0;
+        self.time_for(target - self.curr_power as f32)
+    }
+
+    /// Time until a discharging battery is empty.
+    pub fn time_to_empty(&self) -> Option<Duration> {
+        if self.status != BatteryStatus::Discharging {
+            return None;
+        }
+
+        self.time_for(self.curr_power as f32)
+    }
+
+    fn time_for(&self, capacity: f32) -> Option<Duration> {
+        let rate = self.rate.filter(|r| *r > 0.0)?;
+        if capacity <= 0.0 {
+            return None;
+        }
+
+        Duration::try_from_secs_f32(capacity / rate * 3600.0).ok()
+    }
+}
+
+fn smooth(previous: Option<f32>, rate: f32) -> f32 {
+    match previous {
+        Some(prev) => prev + RATE_SMOOTHING * (rate - prev),
+        None => rate,
+    }
+}
+
+/// Formats an estimate as e.g. `2h 05m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+struct Capacity {
+    curr: u32,
+    total: u32,
+    design: Option<u32>,
+    unit: CapacityUnit,
 }
 
-pub fn find_batteries(power_supply_path: &PathBuf) -> Vec<PathBuf> {
-    fs::read_dir(power_supply_path)
+/// Reads the `energy_*` family, falling back to `charge_*` for batteries that only report µAh
+/// and to `capacity` for peripherals that only report a percentage. Charge values are
+/// converted to energy when the battery reports a voltage.
+fn read_capacity(path: &Path) -> Result<Capacity, (BatteryAttribute, io::Error)> {
+    if path.join(BatteryAttribute::CurrPower.file_name()).exists() {
+        let curr = read_num_battery_attribute(path, BatteryAttribute::CurrPower)
+            .map_err(|e| (BatteryAttribute::CurrPower, e))?;
+        let total = read_num_battery_attribute(path, BatteryAttribute::TotalPower)
+            .map_err(|e| (BatteryAttribute::TotalPower, e))?;
+        let design = read_num_battery_attribute(path, BatteryAttribute::DesignPower).ok();
+
+        return Ok(Capacity {
+            curr,
+            total,
+            design,
+            unit: CapacityUnit::Energy,
+        });
+    }
+
+    if !path.join(BatteryAttribute::CurrCharge.file_name()).exists() {
+        if let Ok(percent) = read_num_battery_attribute(path, BatteryAttribute::Capacity) {
+            return Ok(Capacity {
+                curr: percent,
+                total: 100,
+                design: None,
+                unit: CapacityUnit::Percent,
+            });
+        }
+    }
+
+    let curr: u32 = read_num_battery_attribute(path, BatteryAttribute::CurrCharge)
+        .map_err(|e| (BatteryAttribute::CurrCharge, e))?;
+    let total: u32 = read_num_battery_attribute(path, BatteryAttribute::TotalCharge)
+        .map_err(|e| (BatteryAttribute::TotalCharge, e))?;
+    let design: Option<u32> = read_num_battery_attribute(path, BatteryAttribute::DesignCharge).ok();
+
+    // Prefer the nominal design voltage so energy values don't fluctuate with the live voltage.
+    let voltage: Option<u32> = read_num_battery_attribute(path, BatteryAttribute::VoltageMinDesign)
+        .or_else(|_| read_num_battery_attribute(path, BatteryAttribute::Voltage))
+        .ok()
+        .filter(|v| *v > 0);
+
+    Ok(match voltage {
+        Some(uv) => Capacity {
+            curr: charge_to_energy(curr, uv),
+            total: charge_to_energy(total, uv),
+            design: design.map(|d| charge_to_energy(d, uv)),
+            unit: CapacityUnit::Energy,
+        },
+        None => Capacity {
+            curr,
+            total,
+            design,
+            unit: CapacityUnit::Charge,
+        },
+    })
+}
+
+/// Reads `power_now`, or derives it from `current_now` and `voltage_now`. Some drivers
+/// report a negative value while discharging, so only the magnitude is kept.
+fn read_power_draw(path: &Path) -> Option<u32> {
+    if let Ok(power) = read_num_battery_attribute::<i64>(path, BatteryAttribute::PowerNow) {
+        return Some(power.unsigned_abs().min(u32::MAX as u64) as u32);
+    }
+
+    let current = read_current(path)?;
+    let voltage: u32 = read_num_battery_attribute(path, BatteryAttribute::Voltage).ok()?;
+
+    Some(charge_to_energy(current, voltage))
+}
+
+/// Reads the magnitude of `current_now` in µA.
+fn read_current(path: &Path) -> Option<u32> {
+    let current: i64 = read_num_battery_attribute(path, BatteryAttribute::CurrentNow).ok()?;
+    Some(current.unsigned_abs().min(u32::MAX as u64) as u32)
+}
+
+/// Converts µAh to µWh (or µA to µW) at the given voltage in µV.
+fn charge_to_energy(charge: u32, voltage: u32) -> u32 {
+    (charge as u64 * voltage as u64 / 1_000_000).min(u32::MAX as u64) as u32
+}
+
+pub fn battery_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("unknown")
+}
+
+/// Whether a battery powers the system or a peripheral, from the `scope` attribute.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BatteryScope {
+    System,
+    /// Mice, keyboards, headsets and other peripherals. Their thresholds can't be set.
+    Device,
+}
+
+/// Reads the scope of the power supply at `path`. Laptop batteries usually have no
+/// `scope` attribute at all, so anything but `Device` counts as a system battery.
+pub fn battery_scope(path: &Path) -> BatteryScope {
+    match read_str_battery_attribute(path, BatteryAttribute::Scope) {
+        Ok(scope) if scope.trim() == "Device" => BatteryScope::Device,
+        _ => BatteryScope::System,
+    }
+}
+
+/// Finds the batteries with the given scope, sorted by name so the order is the same
+/// on every run. Entries are recognised by `type == Battery`; supplies without a `type`
+/// attribute fall back to the `BAT*` naming convention.
+pub fn find_batteries(power_supply_path: &Path, scope: BatteryScope) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(power_supply_path)
         .ok()
         .into_iter()
         .flatten()
         .filter_map(Result::ok)
-        .filter(|entry| {
-            entry
-                .file_name()
-                .to_str()
-                .map(|name| name.starts_with("BAT"))
-                .unwrap_or(false)
-        })
         .map(|entry| entry.path())
-        .collect()
+        .filter(|path| is_battery(path) && battery_scope(path) == scope)
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn is_battery(path: &Path) -> bool {
+    match read_str_battery_attribute(path, BatteryAttribute::Type) {
+        Ok(kind) => kind.trim() == "Battery",
+        Err(_) => battery_name(path).starts_with("BAT"),
+    }
 }
 
 fn read_num_battery_attribute<T>(bat_path: &Path, attr: BatteryAttribute) -> io::Result<T>
@@ -187,7 +690,10 @@ where
     })
 }
 
-fn read_str_battery_attribute(bat_path: &Path, attr: BatteryAttribute) -> io::Result<String> {
+pub(crate) fn read_str_battery_attribute(
+    bat_path: &Path,
+    attr: BatteryAttribute,
+) -> io::Result<String> {
     let path = bat_path.join(attr.file_name());
     fs::read_to_string(&path).map_err(|e| {
         io::Error::new(
@@ -196,3 +702,52 @@ fn read_str_battery_attribute(bat_path: &Path, attr: BatteryAttribute) -> io::Re
         )
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, attr: &str, value: &str) {
+        fs::write(dir.join(attr), value).unwrap();
+    }
+
+    fn charge_battery(current: Option<&str>) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "status", "Discharging\n");
+        write(dir.path(), "charge_now", "3000000\n");
+        write(dir.path(), "charge_full", "4000000\n");
+        if let Some(current) = current {
+            write(dir.path(), "current_now", current);
+        }
+        dir
+    }
+
+    #[test]
+    fn keeps_driver_current_over_charge_delta() {
+        let dir = charge_battery(Some("-1000000\n"));
+        let (mut battery, _) = Battery::new(dir.path()).unwrap();
+        assert!(battery.unit == CapacityUnit::Charge);
+        assert_eq!(battery.rate, Some(1_000_000.0));
+
+        for charge in ["2999000", "2998000"] {
+            write(dir.path(), "charge_now", charge);
+            battery.refresh().unwrap();
+        }
+
+        assert_eq!(battery.rate, Some(1_000_000.0));
+    }
+
+    #[test]
+    fn derives_rate_from_charge_delta_without_current() {
+        let dir = charge_battery(None);
+        let (mut battery, _) = Battery::new(dir.path()).unwrap();
+        assert_eq!(battery.rate, None);
+
+        for charge in ["2999000", "2998000"] {
+            write(dir.path(), "charge_now", charge);
+            battery.refresh().unwrap();
+        }
+
+        assert!(battery.rate.is_some_and(|rate| rate > 0.0));
+    }
+}
@@ -0,0 +1,428 @@
+use crate::{
+    backend::ThresholdBackend,
+    battery::{battery_name, Battery, CapacityUnit, ChargeBehaviour},
+    state::{self, PendingCalibration, State},
+    thresholds::{SaveReport, Thresholds},
+    topup::{self, TOPUP_THRESHOLDS},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+/// Thresholds held for the whole calibration, so the battery charges to 100% whenever
+/// the charger is connected.
+pub const CALIBRATION_THRESHOLDS: Thresholds = TOPUP_THRESHOLDS;
+
+/// Charge at which the discharge step is considered complete. Most desktops suspend or
+/// shut down shortly below this, which ends the step just the same.
+const EMPTY_CHARGE: f32 = 5.0;
+
+/// Steps of a calibration, in order.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    Charge,
+    Discharge,
+    Recharge,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Self::Charge, Self::Discharge, Self::Recharge];
+
+    /// 1-based position of the step.
+    pub fn step(&self) -> usize {
+        match self {
+            Self::Charge => 1,
+            Self::Discharge => 2,
+            Self::Recharge => 3,
+        }
+    }
+
+    fn next(&self) -> Option<Self> {
+        match self {
+            Self::Charge => Some(Self::Discharge),
+            Self::Discharge => Some(Self::Recharge),
+            Self::Recharge => None,
+        }
+    }
+
+    /// What the user has to do during the step.
+    pub fn instructions(&self, force_discharge: bool) -> &'static str {
+        match self {
+            Self::Charge | Self::Recharge => {
+                "Connect the charger and leave it connected until the battery is full."
+            }
+            Self::Discharge if force_discharge => {
+                "Leave the charger connected; the battery is being force-discharged."
+            }
+            Self::Discharge => {
+                "Unplug the charger and use the laptop until the battery is nearly empty. Save your work first."
+            }
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Charge => write!(f, "charging to 100%"),
+            Self::Discharge => write!(f, "discharging to empty"),
+            Self::Recharge => write!(f, "recharging to 100%"),
+        }
+    }
+}
+
+/// Outcome of one [`advance`] call.
+pub enum Progress {
+    /// The current step isn't complete yet.
+    Waiting(Phase),
+    /// The previous step completed and this one started.
+    Entered(Phase),
+    Finished(Finished),
+}
+
+/// A completed calibration: the restored thresholds and the full capacity before and
+/// after, in the battery's [`CapacityUnit`].
+pub struct Finished {
+    pub restored: SaveReport,
+    pub capacity_before: u32,
+    pub capacity_after: u32,
+    pub design: Option<u32>,
+    pub unit: CapacityUnit,
+}
+
+impl Finished {
+    /// e.g. "Full capacity 45.2 Wh -> 48.9 Wh (+8.2%), health 84.1% -> 91.0%".
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Full capacity {} -> {}",
+            format_capacity(self.capacity_before, self.unit),
+            format_capacity(self.capacity_after, self.unit)
+        );
+        if self.capacity_before > 0 {
+            summary.push_str(&format!(
+                " ({:+.1}%)",
+                (self.capacity_after as f32 / self.capacity_before as f32 - 1.0) * 100.0
+            ));
+        }
+        if let Some(design) = self.design.filter(|d| *d > 0) {
+            summary.push_str(&format!(
+                ", health {:.1}% -> {:.1}%",
+                self.capacity_before as f32 / design as f32 * 100.0,
+                self.capacity_after as f32 / design as f32 * 100.0
+            ));
+        }
+        summary
+    }
+}
+
+pub fn format_capacity(capacity: u32, unit: CapacityUnit) -> String {
+    match unit {
+        CapacityUnit::Energy => format!("{:.1} Wh", capacity as f32 / 1_000_000.0),
+        CapacityUnit::Charge => format!("{:.0} mAh", capacity as f32 / 1000.0),
+        CapacityUnit::Percent => format!("{}%", capacity),
+    }
+}
+
+/// Whether the discharge step can run on AC through `charge_behaviour`.
+pub fn can_force_discharge(battery_path: &Path) -> bool {
+    ChargeBehaviour::available(battery_path).contains(&ChargeBehaviour::ForceDischarge)
+}
+
+/// Starts calibrating one battery: records the current thresholds, charge behaviour and
+/// full capacity in the state file, then raises the thresholds to
+/// [`CALIBRATION_THRESHOLDS`].
+pub fn start(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<SaveReport> {
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+
+    if state.calibration.contains_key(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("a calibration is already in progress for {}", name),
+        ));
+    }
+    if state.topup.contains_key(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("a top-up is pending for {}; restore it first", name),
+        ));
+    }
+
+    let (battery, _) = Battery::new(battery_path)?;
+    if battery.unit == CapacityUnit::Percent {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} reports no capacity to calibrate", name),
+        ));
+    }
+
+    let pending = PendingCalibration {
+        phase: Phase::Charge,
+        previous: Thresholds::load(battery_path, backend)?,
+        previous_behaviour: ChargeBehaviour::load(battery_path).ok(),
+        capacity_before: battery.total_power,
+        started: state::now(),
+    };
+    state.calibration.insert(name.to_string(), pending.clone());
+    state.save(state_path)?;
+
+    match enter(battery_path, backend, Phase::Charge) {
+        Ok(report) => {
+            state
+                .applied
+                .insert(name.to_string(), report.accepted.clone());
+            state.save(state_path)?;
+            Ok(report)
+        }
+        Err(err) => {
+            if let Some(behaviour) = pending.previous_behaviour {
+                let _ = behaviour.save(battery_path);
+            }
+            state.calibration.remove(name);
+            state.save(state_path)?;
+            Err(err)
+        }
+    }
+}
+
+/// Moves on if the current step completed while nobody was watching, e.g. the battery ran
+/// empty before a reboot, and otherwise writes the settings of the step again, since
+/// firmware may reset the thresholds and charge behaviour on reboot. Returns `None` if the
+/// battery isn't being calibrated.
+pub fn resume(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<Option<Progress>> {
+    let progress = advance(battery_path, backend, state_path)?;
+    if !matches!(progress, Some(Progress::Waiting(_))) {
+        return Ok(progress);
+    }
+
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+    let Some(pending) = state.calibration.get(name).cloned() else {
+        return Ok(None);
+    };
+
+    let report = enter(battery_path, backend, pending.phase)?;
+    state.applied.insert(name.to_string(), report.accepted);
+    state.save(state_path)?;
+
+    Ok(progress)
+}
+
+/// Moves on to the next step once the current one is complete, restoring the previous
+/// thresholds after the last one. Returns `None` if the battery isn't being calibrated.
+pub fn advance(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<Option<Progress>> {
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+
+    let Some(pending) = state.calibration.get_mut(name) else {
+        return Ok(None);
+    };
+
+    let (battery, _) = Battery::new(battery_path)?;
+    let complete = match pending.phase {
+        Phase::Charge | Phase::Recharge => topup::is_full(&battery),
+        Phase::Discharge => battery.charge_percentage() <= EMPTY_CHARGE,
+    };
+    if !complete {
+        return Ok(Some(Progress::Waiting(pending.phase)));
+    }
+
+    let Some(next) = pending.phase.next() else {
+        let pending = pending.clone();
+        let restored = restore_previous(battery_path, backend, &mut state, &pending)?;
+        state.save(state_path)?;
+
+        let (battery, _) = Battery::new(battery_path)?;
+        return Ok(Some(Progress::Finished(Finished {
+            restored,
+            capacity_before: pending.capacity_before,
+            capacity_after: battery.total_power,
+            design: battery.design_power,
+            unit: battery.unit,
+        })));
+    };
+
+    // The step is recorded first so an interrupted write is retried by `resume`
+    pending.phase = next;
+    state.save(state_path)?;
+
+    let report = enter(battery_path, backend, next)?;
+    state.applied.insert(name.to_string(), report.accepted);
+    state.save(state_path)?;
+
+    Ok(Some(Progress::Entered(next)))
+}
+
+/// Cancels the calibration and restores the previous thresholds and charge behaviour.
+/// Returns `None` if the battery isn't being calibrated.
+pub fn abort(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<Option<SaveReport>> {
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+
+    let Some(pending) = state.calibration.get(name).cloned() else {
+        return Ok(None);
+    };
+
+    let report = restore_previous(battery_path, backend, &mut state, &pending)?;
+    state.save(state_path)?;
+
+    Ok(Some(report))
+}
+
+pub fn pending(battery_path: &Path, state_path: &Path) -> io::Result<Option<PendingCalibration>> {
+    let state = State::load(state_path)?;
+    Ok(state.calibration.get(battery_name(battery_path)).cloned())
+}
+
+/// Applies the charge behaviour and thresholds of `phase`. Charging is only inhibited
+/// by the kernel, so every step but a forced discharge uses `auto`. Discharging is never
+/// forced on a battery that is already empty or can't be read.
+fn enter(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    phase: Phase,
+) -> io::Result<SaveReport> {
+    let above_empty = Battery::new(battery_path)
+        .is_ok_and(|(battery, _)| battery.charge_percentage() > EMPTY_CHARGE);
+    let behaviour = match phase {
+        Phase::Discharge if above_empty && can_force_discharge(battery_path) => {
+            ChargeBehaviour::ForceDischarge
+        }
+        _ => ChargeBehaviour::Auto,
+    };
+    if ChargeBehaviour::available(battery_path).contains(&behaviour)
+        && ChargeBehaviour::load(battery_path).ok() != Some(behaviour)
+    {
+        behaviour.save(battery_path)?;
+    }
+
+    CALIBRATION_THRESHOLDS.save(battery_path, backend)
+}
+
+fn restore_previous(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state: &mut State,
+    pending: &PendingCalibration,
+) -> io::Result<SaveReport> {
+    let name = battery_name(battery_path);
+
+    if let Some(behaviour) = pending.previous_behaviour {
+        if ChargeBehaviour::load(battery_path).ok() != Some(behaviour) {
+            behaviour.save(battery_path)?;
+        }
+    }
+
+    let report = pending.previous.save(battery_path, backend)?;
+    state.calibration.remove(name);
+    state
+        .applied
+        .insert(name.to_string(), report.accepted.clone());
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Generic;
+    use std::{fs, path::PathBuf};
+
+    /// A battery in the discharge step of a calibration, at `charge` percent.
+    fn discharging(charge: u32) -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let battery = dir.path().join("BAT0");
+        fs::create_dir_all(&battery).unwrap();
+        for (attr, value) in [
+            ("status", "Discharging".to_string()),
+            ("energy_now", (charge * 500_000).to_string()),
+            ("energy_full", "50000000".to_string()),
+            ("charge_control_start_threshold", "95".to_string()),
+            ("charge_control_end_threshold", "100".to_string()),
+            (
+                "charge_behaviour",
+                "[auto] inhibit-charge force-discharge".to_string(),
+            ),
+        ] {
+            fs::write(battery.join(attr), value).unwrap();
+        }
+
+        let state_path = dir.path().join("state.toml");
+        let mut state = State::default();
+        state.calibration.insert(
+            "BAT0".to_string(),
+            PendingCalibration {
+                phase: Phase::Discharge,
+                previous: Thresholds { start: 40, end: 80 },
+                previous_behaviour: Some(ChargeBehaviour::Auto),
+                capacity_before: 50_000_000,
+                started: 0,
+            },
+        );
+        state.save(&state_path).unwrap();
+
+        (dir, battery, state_path)
+    }
+
+    #[test]
+    fn resume_moves_past_a_discharge_that_finished_unattended() {
+        let (_dir, battery, state_path) = discharging(3);
+
+        let progress = resume(&battery, &Generic, &state_path).unwrap();
+
+        assert!(matches!(progress, Some(Progress::Entered(Phase::Recharge))));
+        assert_eq!(
+            ChargeBehaviour::load(&battery).unwrap(),
+            ChargeBehaviour::Auto
+        );
+        let state = State::load(&state_path).unwrap();
+        assert!(state.calibration["BAT0"].phase == Phase::Recharge);
+    }
+
+    #[test]
+    fn resume_reapplies_an_unfinished_step() {
+        let (_dir, battery, state_path) = discharging(50);
+        fs::write(battery.join("charge_control_end_threshold"), "80").unwrap();
+        fs::write(battery.join("charge_control_start_threshold"), "40").unwrap();
+
+        let progress = resume(&battery, &Generic, &state_path).unwrap();
+
+        assert!(matches!(
+            progress,
+            Some(Progress::Waiting(Phase::Discharge))
+        ));
+        assert_eq!(
+            ChargeBehaviour::load(&battery).unwrap(),
+            ChargeBehaviour::ForceDischarge
+        );
+        assert_eq!(Generic.load(&battery).unwrap(), CALIBRATION_THRESHOLDS);
+    }
+
+    #[test]
+    fn never_forces_discharge_when_empty() {
+        let (_dir, battery, _) = discharging(4);
+
+        enter(&battery, &Generic, Phase::Discharge).unwrap();
+
+        assert_eq!(
+            ChargeBehaviour::load(&battery).unwrap(),
+            ChargeBehaviour::Auto
+        );
+    }
+}
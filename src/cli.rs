@@ -1,26 +1,446 @@
-use clap::Parser;
+use crate::{
+    backend::BackendKind,
+    battery::ChargeBehaviour,
+    history::DEFAULT_KEEP_MONTHS,
+    report::OutputFormat,
+    thresholds::{ThresholdKind, Thresholds},
+    topup::{DEFAULT_TIMEOUT_HOURS, MAX_TIMEOUT_HOURS},
+    watch::{EventKind, SinkKind},
+};
+use clap::{ArgGroup, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(
     version,
-    about = "Set or read battery charge threshold on ASUS laptops"
+    about = "Set or read battery charge thresholds on Linux laptops"
 )]
 pub struct Cli {
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub path: Option<PathBuf>,
 
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "System config file, where thresholds and profiles are stored [default: /etc/batty/config.toml]"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "KEY=VALUE",
+        help = "Override a config value, e.g. tui.refresh-ms=500 (repeatable)"
+    )]
+    pub set: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "State file for pending operations [default: /var/lib/batty/state.toml]"
+    )]
+    pub state: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Directory of the battery history [default: /var/lib/batty/history]"
+    )]
+    pub history_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Threshold interface to use instead of detecting it from DMI and sysfs"
+    )]
+    pub backend: Option<BackendKind>,
+
+    #[arg(
+        short,
+        long,
+        hide = true,
+        help = "Deprecated: use `batty set --start` or `batty set --end`"
+    )]
     pub value: Option<u8>,
 
     #[arg(
-        short = 'k',
+        short,
         long,
-        default_value = "end",
-        help = "Which threshold kind to set (start or end)"
+        value_enum,
+        hide = true,
+        help = "Deprecated: threshold set by --value [default: end]"
     )]
-    pub kind: String,
+    pub kind: Option<ThresholdKind>,
 
-    #[arg(long, help = "Launch the interactive terminal UI")]
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        hide = true,
+        conflicts_with_all = ["value", "kind"],
+        help = "Deprecated: use `batty set --start`"
+    )]
+    pub start: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        hide = true,
+        conflicts_with_all = ["value", "kind"],
+        help = "Deprecated: use `batty set --end`"
+    )]
+    pub end: Option<u8>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Battery to operate on, e.g. BAT1 (repeatable) [default: first battery]"
+    )]
+    pub battery: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "battery",
+        help = "Operate on every battery"
+    )]
+    pub all: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Update the remaining batteries when one of them fails instead of changing none"
+    )]
+    pub keep_going: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Also show peripheral batteries such as mice and headsets (read-only)"
+    )]
+    pub devices: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "BEHAVIOUR",
+        hide = true,
+        help = "Deprecated: use `batty set --charge-behaviour`"
+    )]
+    pub charge_behaviour: Option<ChargeBehaviour>,
+
+    #[arg(long, hide = true, help = "Deprecated: use `batty tui`")]
     pub tui: bool,
+
+    #[arg(
+        short,
+        long,
+        value_enum,
+        hide = true,
+        help = "Deprecated: use `batty status --format`"
+    )]
+    pub format: Option<OutputFormat>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    #[command(
+        about = "Show the thresholds and charge behaviour (the default), or report every battery with --format"
+    )]
+    Status {
+        #[arg(
+            short,
+            long,
+            value_enum,
+            help = "Report every battery in the given format instead"
+        )]
+        format: Option<OutputFormat>,
+    },
+
+    #[command(about = "Print the thresholds as START-END, or one of them with --kind")]
+    Get {
+        #[arg(short, long, value_enum)]
+        kind: Option<ThresholdKind>,
+    },
+
+    #[command(
+        about = "Change the thresholds and charge behaviour; start and end are written together"
+    )]
+    #[command(group(
+        ArgGroup::new("change")
+            .required(true)
+            .multiple(true)
+            .args(["range", "start", "end", "charge_behaviour"])
+    ))]
+    Set {
+        #[arg(
+            value_name = "START-END",
+            conflicts_with_all = ["start", "end"],
+            help = "Both thresholds, e.g. 85-95"
+        )]
+        range: Option<Thresholds>,
+
+        #[arg(short, long, value_name = "PERCENT")]
+        start: Option<u8>,
+
+        #[arg(short, long, value_name = "PERCENT")]
+        end: Option<u8>,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "BEHAVIOUR",
+            help = "Kernel charge behaviour, e.g. inhibit-charge to run on AC without charging"
+        )]
+        charge_behaviour: Option<ChargeBehaviour>,
+    },
+
+    #[command(about = "Launch the interactive terminal UI")]
+    Tui,
+
+    #[command(about = "List the laptop's batteries with their charge, thresholds and backend")]
+    List,
+
+    #[command(about = "Write the thresholds stored in the config file to every battery")]
+    Apply,
+
+    #[command(
+        about = "Install a systemd unit and sleep hook that run `batty apply` at boot and resume"
+    )]
+    InstallService {
+        #[arg(long, help = "Print the generated files instead of writing them")]
+        dry_run: bool,
+
+        #[arg(
+            long,
+            help = "Also install the D-Bus service, its bus policy and polkit action"
+        )]
+        daemon: bool,
+    },
+
+    #[command(
+        about = "Write udev rules and a tmpfiles.d entry letting a group change thresholds without root"
+    )]
+    SetupPermissions {
+        #[arg(
+            long,
+            default_value = "batty",
+            help = "Group granted write access to the threshold files"
+        )]
+        group: String,
+
+        #[arg(long, help = "Print the generated files instead of writing them")]
+        dry_run: bool,
+
+        #[arg(long, help = "Remove the generated files")]
+        uninstall: bool,
+    },
+
+    #[command(
+        about = "Charge to 100% once, then restore the current thresholds when full or after a timeout"
+    )]
+    Topup {
+        #[arg(
+            long,
+            default_value_t = DEFAULT_TIMEOUT_HOURS,
+            value_parser = clap::value_parser!(u64).range(1..=MAX_TIMEOUT_HOURS),
+            help = "Hours before the thresholds are restored anyway"
+        )]
+        timeout: u64,
+
+        #[arg(
+            long,
+            help = "Return immediately; the thresholds are restored by the next `batty apply`"
+        )]
+        no_wait: bool,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["timeout", "no_wait"],
+            help = "Restore the thresholds now and cancel the top-up"
+        )]
+        restore: bool,
+    },
+
+    #[command(
+        about = "Recalibrate the battery gauge: charge to 100%, discharge fully and recharge, then restore the thresholds"
+    )]
+    Calibrate {
+        #[arg(long, help = "Cancel the calibration and restore the thresholds")]
+        abort: bool,
+    },
+
+    #[command(about = "Record samples of every battery to the history store until stopped")]
+    Log {
+        #[arg(long, default_value_t = 60, help = "Seconds between samples")]
+        interval: u64,
+
+        #[arg(
+            long,
+            default_value_t = DEFAULT_KEEP_MONTHS,
+            help = "Months of history to keep"
+        )]
+        keep_months: u32,
+    },
+
+    #[command(
+        about = "Monitor every battery and send notifications for low charge, threshold resets and more"
+    )]
+    Watch {
+        #[arg(long, help = "Seconds between checks [default: 60]")]
+        interval: Option<u64>,
+
+        #[arg(
+            long,
+            help = "Charge percentage that triggers the low event [default: 20]"
+        )]
+        low: Option<u8>,
+
+        #[arg(
+            long,
+            help = "Charge percentage that triggers the critical event [default: 5]"
+        )]
+        critical: Option<u8>,
+
+        #[arg(
+            long,
+            help = "Health percentage that triggers the health event [default: 80]"
+        )]
+        health_below: Option<u8>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Event to report (repeatable) [default: all events]"
+        )]
+        event: Vec<EventKind>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Where to deliver events (repeatable) [default: desktop and stderr]"
+        )]
+        sink: Vec<SinkKind>,
+
+        #[arg(
+            long,
+            value_name = "CMD",
+            help = "Shell command run for every event; enables the hook sink"
+        )]
+        hook: Option<String>,
+
+        #[arg(
+            long,
+            help = "Write the last applied thresholds back when firmware changes them"
+        )]
+        reapply: bool,
+
+        #[arg(long, help = "Check once and exit instead of monitoring")]
+        once: bool,
+    },
+
+    #[command(about = "Serve battery state and threshold control on D-Bus as org.batty.Manager")]
+    Daemon {
+        #[arg(
+            long,
+            help = "Use the session bus instead of the system bus; polkit isn't consulted"
+        )]
+        session: bool,
+
+        #[arg(
+            long,
+            default_value_t = 5,
+            help = "Seconds between battery reads for property change signals"
+        )]
+        interval: u64,
+    },
+
+    #[command(
+        about = "Compare every battery's thresholds with the ones batty last applied; exits non-zero on a mismatch"
+    )]
+    Check,
+
+    #[command(about = "Show recorded battery history")]
+    History {
+        #[arg(
+            long,
+            default_value = "24h",
+            help = "Start of the range: an age like 12h or 30d, a date or a Unix timestamp"
+        )]
+        since: String,
+
+        #[arg(long, help = "End of the range [default: now]")]
+        until: Option<String>,
+
+        #[arg(long, help = "Print summary statistics instead of individual samples")]
+        stats: bool,
+    },
+
+    #[command(about = "List the batteries of mice, keyboards, headsets and other peripherals")]
+    Devices {
+        #[arg(
+            short,
+            long,
+            value_enum,
+            help = "Print in the given format instead of a table"
+        )]
+        format: Option<OutputFormat>,
+    },
+
+    #[command(about = "Manage named threshold presets")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    #[command(about = "Show, check or edit the layered configuration")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigAction {
+    #[command(about = "Print every effective value and the layer that set it")]
+    Show,
+
+    #[command(about = "Check every layer and the merged config; exits non-zero on a problem")]
+    Validate,
+
+    #[command(about = "Open the user config in $VISUAL or $EDITOR and check it afterwards")]
+    Edit {
+        #[arg(long, help = "Edit the system config instead")]
+        system: bool,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ProfileAction {
+    #[command(about = "List built-in and saved profiles")]
+    List,
+
+    #[command(about = "Show the thresholds of a profile")]
+    Show { name: String },
+
+    #[command(about = "Apply a profile to the selected batteries")]
+    Apply { name: String },
+
+    #[command(about = "Save thresholds as a profile (defaults to the battery's current values)")]
+    Save {
+        name: String,
+
+        #[arg(long)]
+        start: Option<u8>,
+
+        #[arg(long)]
+        end: Option<u8>,
+    },
 }
@@ -0,0 +1,714 @@
+use crate::{backend::BackendKind, thresholds::Thresholds, tui::TuiConfig, watch::WatchConfig};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/batty/config.toml";
+pub const DEFAULT_POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+/// Group-writable store created by `batty setup-permissions`. Thresholds and profiles
+/// are saved here while it exists, so the group can save them without being able to
+/// change the rest of the config.
+pub const SHARED_STORE_PATH: &str = "/var/lib/batty/thresholds.toml";
+
+/// The only sections the shared store may set; anything else, such as `watch.hook`,
+/// would let the group run commands as root.
+const SHARED_SECTIONS: [&str; 2] = ["batteries", "profiles"];
+
+/// Environment variables that override a config key.
+pub const ENV_OVERRIDES: [(&str, &str); 5] = [
+    ("BATTY_PATH", "path"),
+    ("BATTY_BACKEND", "backend"),
+    ("BATTY_TUI_REFRESH_MS", "tui.refresh-ms"),
+    ("BATTY_WATCH_INTERVAL", "watch.interval"),
+    ("BATTY_WATCH_HOOK", "watch.hook"),
+];
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The sysfs `power_supply` directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Threshold interface to use instead of detecting it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    #[serde(default)]
+    pub batteries: BTreeMap<String, Thresholds>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Thresholds>,
+    /// Settings for `batty watch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch: Option<WatchConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tui: Option<TuiConfig>,
+}
+
+impl Config {
+    /// Reads the config at `path`. A missing file yields an empty config.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Every setting at its default, including the optional sections, as the bottom layer.
+    fn defaults() -> Self {
+        Self {
+            path: Some(PathBuf::from(DEFAULT_POWER_SUPPLY_PATH)),
+            watch: Some(WatchConfig::default()),
+            tui: Some(TuiConfig::default()),
+            ..Self::default()
+        }
+    }
+
+    pub fn power_supply_path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_POWER_SUPPLY_PATH))
+    }
+
+    /// Checks values the types alone don't constrain.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for (section, entries) in [("batteries", &self.batteries), ("profiles", &self.profiles)] {
+            for (name, thresholds) in entries {
+                if let Err(e) = thresholds.validate() {
+                    problems.push(Problem::new(&[&format!("{}.{}", section, name)], e));
+                }
+            }
+        }
+        if let Some(watch) = &self.watch {
+            problems.extend(watch.validate());
+        }
+        if let Some(tui) = &self.tui {
+            problems.extend(tui.validate());
+        }
+
+        problems
+    }
+}
+
+/// A value that parses but can't be used, with the keys involved.
+pub struct Problem {
+    pub keys: Vec<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(keys: &[&str], message: impl Into<String>) -> Self {
+        Self {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Where a config value came from.
+#[derive(Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+    /// A command line option, e.g. `--backend` or `--set tui.refresh-ms`.
+    Cli(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Env(var) => write!(f, "${}", var),
+            Self::Cli(option) => write!(f, "{}", option),
+        }
+    }
+}
+
+/// One value set from the environment or the command line.
+pub struct Override {
+    pub source: Source,
+    /// Dotted key, e.g. `tui.refresh-ms`.
+    pub key: String,
+    pub value: toml::Value,
+}
+
+impl Override {
+    /// Parses `value` as a TOML value, falling back to a plain string so paths and names
+    /// don't need quoting.
+    pub fn new(source: Source, key: &str, value: &str) -> Self {
+        let value = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        Self {
+            source,
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    /// Parses a `KEY=VALUE` argument of `--set`.
+    pub fn parse_assignment(assignment: &str) -> Result<Self, String> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got {}", assignment))?;
+        let key = key.trim();
+
+        Ok(Self::new(
+            Source::Cli(format!("--set {}", key)),
+            key,
+            value.trim(),
+        ))
+    }
+}
+
+/// The config layers, lowest precedence first: defaults, the system file, the shared
+/// store, the user file, then environment and command line overrides. Thresholds and
+/// profiles are only ever written to `store`, see [`store_path`].
+pub struct ConfigLayers {
+    pub system: PathBuf,
+    /// The shared store when it is where thresholds are saved; limited to
+    /// [`SHARED_SECTIONS`].
+    pub shared: Option<PathBuf>,
+    pub user: Option<PathBuf>,
+    pub store: PathBuf,
+    pub overrides: Vec<Override>,
+}
+
+/// The merged config and the layer that set each value, by dotted key.
+pub struct Resolved {
+    pub config: Config,
+    pub sources: BTreeMap<String, Source>,
+    values: toml::Table,
+}
+
+impl ConfigLayers {
+    /// Layers for the system file at `system`, the user's XDG config file and the
+    /// overrides in [`ENV_OVERRIDES`], followed by `cli` overrides. Thresholds and
+    /// profiles are saved to `store`.
+    pub fn new(system: PathBuf, store: PathBuf, cli: Vec<Override>) -> Self {
+        let mut overrides: Vec<Override> = ENV_OVERRIDES
+            .iter()
+            .filter_map(|(var, key)| {
+                let value = env::var(var).ok()?;
+                Some(Override::new(Source::Env(var), key, &value))
+            })
+            .collect();
+        overrides.extend(cli);
+
+        Self {
+            shared: (store != system).then(|| store.clone()),
+            system,
+            user: user_config_path(),
+            store,
+            overrides,
+        }
+    }
+
+    pub fn files(&self) -> Vec<&Path> {
+        std::iter::once(self.system.as_path())
+            .chain(self.shared.as_deref())
+            .chain(self.user.as_deref())
+            .collect()
+    }
+
+    pub fn load(&self) -> io::Result<Config> {
+        self.resolve().map(|resolved| resolved.config)
+    }
+
+    pub fn resolve(&self) -> io::Result<Resolved> {
+        let mut values = toml::Table::new();
+        let mut sources = BTreeMap::new();
+
+        let defaults = toml::Table::try_from(Config::defaults())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        merge(
+            &mut values,
+            defaults.clone(),
+            &Source::Default,
+            "",
+            &mut sources,
+        );
+
+        for path in self.files() {
+            if let Some(table) = read_table(path)? {
+                if Some(path) == self.shared.as_deref() {
+                    check_shared(path, &table)?;
+                }
+                merge(
+                    &mut values,
+                    table,
+                    &Source::File(path.to_path_buf()),
+                    "",
+                    &mut sources,
+                );
+            }
+        }
+
+        for o in &self.overrides {
+            // Applied to the defaults alone first, so a bad value is blamed on its override
+            let mut alone = defaults.clone();
+            set_key(&mut alone, &o.key, o.value.clone())
+                .and_then(|_| {
+                    Config::deserialize(toml::Value::Table(alone))
+                        .map(drop)
+                        .map_err(|e| e.to_string())
+                })
+                .and_then(|_| set_key(&mut values, &o.key, o.value.clone()))
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid {}: {}", o.source, e.trim_end().replace('\n', " ")),
+                    )
+                })?;
+            sources.retain(|key, _| !is_under(key, &o.key));
+            record_leaves(&o.key, &o.value, &o.source, &mut sources);
+        }
+
+        let config = Config::deserialize(toml::Value::Table(values.clone())).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config: {}", e.to_string().trim_end()),
+            )
+        })?;
+
+        Ok(Resolved {
+            config,
+            sources,
+            values,
+        })
+    }
+
+    /// Layers above `store` that replace the thresholds stored there under `key`, e.g.
+    /// `[batteries.BAT0]` in the user file hiding what `batty set` wrote to the system
+    /// file. `batty apply` uses the merged value.
+    pub fn overriding(&self, key: &str) -> io::Result<Vec<Source>> {
+        self.resolve()
+            .map(|resolved| resolved.overriding(&self.store, key))
+    }
+
+    /// Every battery and profile in the file at `path` that a layer above it replaces,
+    /// with the layers replacing it.
+    pub fn overridden(&self, path: &Path) -> io::Result<Vec<(String, Vec<Source>)>> {
+        let Some(table) = read_table(path)? else {
+            return Ok(Vec::new());
+        };
+
+        let resolved = self.resolve()?;
+        let mut overridden = Vec::new();
+        for section in ["batteries", "profiles"] {
+            let Some(toml::Value::Table(entries)) = table.get(section) else {
+                continue;
+            };
+            for name in entries.keys() {
+                let key = format!("{}.{}", section, name);
+                let sources = resolved.overriding(path, &key);
+                if !sources.is_empty() {
+                    overridden.push((key, sources));
+                }
+            }
+        }
+        Ok(overridden)
+    }
+}
+
+impl Resolved {
+    /// Every value as a dotted key, its TOML representation and its source, in key order.
+    pub fn entries(&self) -> Vec<(String, String, &Source)> {
+        let mut leaves = Vec::new();
+        flatten(&self.values, "", &mut leaves);
+
+        leaves
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let source = self.sources.get(&key)?;
+                Some((key, value.to_string(), source))
+            })
+            .collect()
+    }
+
+    /// The layers other than the file at `path` and the defaults that set `key` or
+    /// anything below it.
+    fn overriding(&self, path: &Path, key: &str) -> Vec<Source> {
+        let stored = Source::File(path.to_path_buf());
+        self.sources_of(key)
+            .into_iter()
+            .filter(|source| **source != stored && **source != Source::Default)
+            .cloned()
+            .collect()
+    }
+
+    /// The distinct layers that set `key` or anything below it.
+    pub fn sources_of(&self, key: &str) -> Vec<&Source> {
+        let mut sources: Vec<&Source> = Vec::new();
+        for (_, source) in self.sources.iter().filter(|(k, _)| is_under(k, key)) {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
+}
+
+/// Reads one config file as a table. A missing file yields `None`.
+pub fn read_table(path: &Path) -> io::Result<Option<toml::Table>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    contents.parse::<toml::Table>().map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid config {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Fails when the shared store at `path` sets anything but [`SHARED_SECTIONS`].
+fn check_shared(path: &Path, table: &toml::Table) -> io::Result<()> {
+    match table
+        .keys()
+        .find(|key| !SHARED_SECTIONS.contains(&key.as_str()))
+    {
+        Some(key) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid config {}: only [batteries] and [profiles] may be set here, found {}",
+                path.display(),
+                key
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Merges `src` into `dst` table by table; any other value replaces the one below it.
+fn merge(
+    dst: &mut toml::Table,
+    src: toml::Table,
+    source: &Source,
+    prefix: &str,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    for (name, value) in src {
+        let key = join_key(prefix, &name);
+        match (dst.get_mut(&name), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge(existing, table, source, &key, sources);
+            }
+            (_, value) => {
+                sources.retain(|k, _| !is_under(k, &key));
+                record_leaves(&key, &value, source, sources);
+                dst.insert(name, value);
+            }
+        }
+    }
+}
+
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<(), String> {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().filter(|k| !k.is_empty()).ok_or("empty key")?;
+
+    let mut table = table;
+    for part in parts {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("{} is not a table", part))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+fn record_leaves(
+    key: &str,
+    value: &toml::Value,
+    source: &Source,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (name, value) in table {
+                record_leaves(&join_key(key, name), value, source, sources);
+            }
+        }
+        _ => {
+            sources.insert(key.to_string(), source.clone());
+        }
+    }
+}
+
+fn flatten<'a>(table: &'a toml::Table, prefix: &str, leaves: &mut Vec<(String, &'a toml::Value)>) {
+    for (name, value) in table {
+        let key = join_key(prefix, name);
+        match value {
+            toml::Value::Table(table) => flatten(table, &key, leaves),
+            _ => leaves.push((key, value)),
+        }
+    }
+}
+
+fn join_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+fn is_under(key: &str, prefix: &str) -> bool {
+    key == prefix
+        || key
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+pub fn config_path(path: Option<PathBuf>) -> PathBuf {
+    path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+/// Where thresholds and profiles are saved: `path` when given, the shared store when
+/// `batty setup-permissions` created it and otherwise the system config. Root saves to the
+/// shared store too, so there is only ever one copy for `batty apply` to restore.
+pub fn store_path(path: Option<PathBuf>) -> PathBuf {
+    match path {
+        Some(path) => path,
+        None if Path::new(SHARED_STORE_PATH).exists() => PathBuf::from(SHARED_STORE_PATH),
+        None => PathBuf::from(DEFAULT_CONFIG_PATH),
+    }
+}
+
+/// Explains a failure to store thresholds in `path`, after which `batty apply` restores
+/// the previous ones at boot and on resume.
+pub fn store_error(path: &Path, e: &io::Error) -> String {
+    let mut message = format!(
+        "failed to store thresholds in {}: {}; they will be lost on reboot or resume",
+        path.display(),
+        e
+    );
+    if e.kind() == io::ErrorKind::PermissionDenied {
+        message.push_str(" (run `batty setup-permissions` as root to let a group store them)");
+    }
+    message
+}
+
+/// `$XDG_CONFIG_HOME/batty/config.toml`, falling back to `~/.config`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("batty/config.toml"))
+}
+
+/// Records `thresholds` for `name` so that `batty apply` restores them later.
+pub fn persist_thresholds(
+    config_path: &Path,
+    name: &str,
+    thresholds: &Thresholds,
+) -> io::Result<()> {
+    persist_entry(config_path, "batteries", name, thresholds)
+}
+
+/// Stores `thresholds` as the profile `name`.
+pub fn persist_profile(config_path: &Path, name: &str, thresholds: &Thresholds) -> io::Result<()> {
+    persist_entry(config_path, "profiles", name, thresholds)
+}
+
+/// Writes `thresholds` to `[section.name]` of the file at `path`, leaving the rest of the
+/// file, comments and formatting included, as it was.
+fn persist_entry(
+    path: &Path,
+    section: &str,
+    name: &str,
+    thresholds: &Thresholds,
+) -> io::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid config {}: {}", path.display(), message),
+        )
+    };
+
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| invalid(e.to_string()))?;
+    let entries = document
+        .entry(section)
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_like_mut()
+        .ok_or_else(|| invalid(format!("{} is not a table", section)))?;
+
+    match entries
+        .get_mut(name)
+        .and_then(|entry| entry.as_table_like_mut())
+    {
+        Some(entry) => {
+            set_number(entry, "start", thresholds.start);
+            set_number(entry, "end", thresholds.end);
+        }
+        None => {
+            let mut table = toml_edit::Table::new();
+            table.insert("start", toml_edit::value(i64::from(thresholds.start)));
+            table.insert("end", toml_edit::value(i64::from(thresholds.end)));
+            entries.insert(name, toml_edit::Item::Table(table));
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, document.to_string())
+}
+
+/// Replaces `key` in `table`, keeping the comments and spacing around the old value.
+fn set_number(table: &mut dyn toml_edit::TableLike, key: &str, number: u8) {
+    let mut value = toml_edit::Value::from(i64::from(number));
+    if let Some(old) = table.get(key).and_then(|item| item.as_value()) {
+        *value.decor_mut() = old.decor().clone();
+    }
+    table.insert(key, toml_edit::Item::Value(value));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persisting_keeps_the_rest_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "# Laptop settings\n\
+             backend = \"generic\"\n\
+             \n\
+             [batteries.BAT0]\n\
+             start = 40 # keeps the cells healthy\n\
+             end = 80\n\
+             \n\
+             [watch]\n\
+             # Check every minute\n\
+             interval = 60\n",
+        )
+        .unwrap();
+
+        persist_thresholds(&path, "BAT0", &Thresholds { start: 75, end: 90 }).unwrap();
+        persist_profile(
+            &path,
+            "travel",
+            &Thresholds {
+                start: 95,
+                end: 100,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Laptop settings\n\
+             backend = \"generic\"\n\
+             \n\
+             [batteries.BAT0]\n\
+             start = 75 # keeps the cells healthy\n\
+             end = 90\n\
+             \n\
+             [watch]\n\
+             # Check every minute\n\
+             interval = 60\n\
+             \n\
+             [profiles.travel]\n\
+             start = 95\n\
+             end = 100\n"
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.batteries["BAT0"], Thresholds { start: 75, end: 90 });
+        assert_eq!(
+            config.profiles["travel"],
+            Thresholds {
+                start: 95,
+                end: 100
+            }
+        );
+    }
+
+    #[test]
+    fn finds_user_thresholds_hiding_stored_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        fs::write(&user, "[batteries.BAT0]\nstart = 40\nend = 80\n").unwrap();
+
+        let layers = ConfigLayers {
+            system: system.clone(),
+            shared: None,
+            user: Some(user.clone()),
+            store: system.clone(),
+            overrides: Vec::new(),
+        };
+        persist_thresholds(&system, "BAT0", &Thresholds { start: 75, end: 90 }).unwrap();
+        persist_thresholds(&system, "BAT1", &Thresholds { start: 75, end: 90 }).unwrap();
+
+        assert!(layers.overriding("batteries.BAT0").unwrap() == [Source::File(user.clone())]);
+        assert!(layers.overriding("batteries.BAT1").unwrap().is_empty());
+        let overridden = layers.overridden(&system).unwrap();
+        assert_eq!(overridden.len(), 1);
+        assert_eq!(overridden[0].0, "batteries.BAT0");
+        assert!(layers.overridden(&user).unwrap().is_empty());
+    }
+
+    #[test]
+    fn shared_store_only_holds_thresholds_and_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("config.toml");
+        let shared = dir.path().join("thresholds.toml");
+        fs::write(&system, "[batteries.BAT0]\nstart = 40\nend = 80\n").unwrap();
+
+        let layers = ConfigLayers::new(system.clone(), shared.clone(), Vec::new());
+        assert_eq!(layers.shared.as_deref(), Some(shared.as_path()));
+
+        persist_thresholds(&layers.store, "BAT0", &Thresholds { start: 60, end: 90 }).unwrap();
+        let config = layers.load().unwrap();
+        assert_eq!(config.batteries["BAT0"], Thresholds { start: 60, end: 90 });
+
+        fs::write(&shared, "[watch]\nhook = \"touch /tmp/owned\"\n").unwrap();
+        let err = layers.load().err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("only [batteries] and [profiles] may be set here, found watch"),
+            "{}",
+            err
+        );
+
+        // An explicit --config is both the system file and the store
+        let layers = ConfigLayers::new(system.clone(), system.clone(), Vec::new());
+        assert!(layers.shared.is_none());
+    }
+}
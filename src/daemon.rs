@@ -0,0 +1,711 @@
+use crate::{
+    backend::Backends,
+    battery::{battery_name, Battery},
+    config::{self, ConfigLayers},
+    profile::Profile,
+    state::{self, State},
+    thresholds::Thresholds,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use zbus::{
+    blocking::connection,
+    fdo, interface,
+    message::Header,
+    object_server::SignalEmitter,
+    zvariant::{OwnedObjectPath, Value},
+    Connection, ObjectServer,
+};
+
+pub const BUS_NAME: &str = "org.batty.Manager";
+pub const MANAGER_PATH: &str = "/org/batty/Manager";
+const BATTERY_PATH_PREFIX: &str = "/org/batty/Battery";
+
+/// polkit action guarding every method that writes thresholds.
+pub const SET_THRESHOLDS_ACTION: &str = "org.batty.set-thresholds";
+
+/// Which bus `batty daemon` serves on. The system bus address can be overridden with
+/// `DBUS_SYSTEM_BUS_ADDRESS`, e.g. to run against a private `dbus-daemon`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bus {
+    System,
+    /// Only the user owning the session bus can call in, so polkit isn't consulted.
+    Session,
+}
+
+/// Everything the D-Bus objects need to read and write thresholds, shared between them.
+struct Context {
+    bat_paths: Vec<PathBuf>,
+    /// Re-read for every profile call so edits apply without a restart.
+    config: ConfigLayers,
+    state_path: PathBuf,
+    backends: Backends,
+    bus: Bus,
+}
+
+impl Context {
+    fn battery_path(&self, name: &str) -> fdo::Result<&PathBuf> {
+        self.bat_paths
+            .iter()
+            .find(|p| battery_name(p) == name)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no battery named {}", name)))
+    }
+
+    /// Asks polkit whether the caller of the current method may change thresholds,
+    /// letting it prompt for a password if the policy requires one.
+    async fn authorize(&self, header: &Header<'_>, connection: &Connection) -> fdo::Result<()> {
+        if self.bus == Bus::Session {
+            return Ok(());
+        }
+
+        let sender = header
+            .sender()
+            .ok_or_else(|| fdo::Error::AccessDenied("caller has no bus name".to_string()))?;
+
+        let mut subject: HashMap<&str, Value> = HashMap::new();
+        subject.insert("name", Value::from(sender.as_str()));
+        let details: HashMap<&str, &str> = HashMap::new();
+        // 1 = AllowUserInteraction
+        let flags = 1u32;
+
+        let reply = connection
+            .call_method(
+                Some("org.freedesktop.PolicyKit1"),
+                "/org/freedesktop/PolicyKit1/Authority",
+                Some("org.freedesktop.PolicyKit1.Authority"),
+                "CheckAuthorization",
+                &(
+                    ("system-bus-name", subject),
+                    SET_THRESHOLDS_ACTION,
+                    details,
+                    flags,
+                    "",
+                ),
+            )
+            .await
+            .map_err(|e| fdo::Error::AccessDenied(format!("polkit check failed: {}", e)))?;
+
+        let (authorized, _, _): (bool, bool, HashMap<String, String>) = reply
+            .body()
+            .deserialize()
+            .map_err(|e| fdo::Error::AccessDenied(format!("polkit check failed: {}", e)))?;
+
+        if !authorized {
+            return Err(fdo::Error::AccessDenied(
+                "not authorized to change battery thresholds".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates the pair with [`Thresholds::with`], writes it and records it like the CLI
+    /// does. Refused while a top-up or calibration is pending.
+    fn save(&self, name: &str, start: u8, end: u8) -> fdo::Result<Thresholds> {
+        let path = self.battery_path(name)?;
+        State::load(&self.state_path)
+            .and_then(|state| state.ensure_idle(name))
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        let backend = self.backends.for_battery(path);
+        let current = Thresholds::load(path, backend.as_ref())
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+        let thresholds = current
+            .with(Some(start), Some(end))
+            .map_err(fdo::Error::InvalidArgs)?;
+        let report = thresholds
+            .save(path, backend.as_ref())
+            .map_err(|e| fdo::Error::Failed(format!("failed to save thresholds: {}", e)))?;
+
+        let key = format!("batteries.{}", name);
+        match config::persist_thresholds(&self.config.store, name, &report.accepted) {
+            Ok(()) => {
+                for source in self.config.overriding(&key).unwrap_or_default() {
+                    eprintln!(
+                        "Warning: {} stored in {} is overridden by {}",
+                        key,
+                        self.config.store.display(),
+                        source
+                    );
+                }
+            }
+            Err(e) => eprintln!(
+                "Warning: {}: {}",
+                name,
+                config::store_error(&self.config.store, &e)
+            ),
+        }
+        if let Err(e) = state::record_applied(&self.state_path, name, &report.accepted) {
+            eprintln!("Warning: failed to record thresholds for {}: {}", name, e);
+        }
+
+        Ok(report.accepted)
+    }
+}
+
+/// Object path of a battery's `org.batty.Battery` object. Bytes D-Bus doesn't allow in
+/// paths, and `_` itself, are escaped as `_` and two hex digits so that every name gets
+/// its own path, e.g. `BAT-0` becomes `BAT_2d0`.
+fn battery_object_path(name: &str) -> String {
+    let mut path = format!("{}/", BATTERY_PATH_PREFIX);
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            path.push(byte as char);
+        } else {
+            path.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    path
+}
+
+struct Manager {
+    context: Arc<Context>,
+}
+
+#[interface(name = "org.batty.Manager")]
+impl Manager {
+    fn list_batteries(&self) -> Vec<String> {
+        self.context
+            .bat_paths
+            .iter()
+            .map(|p| battery_name(p).to_string())
+            .collect()
+    }
+
+    fn get_battery(&self, name: &str) -> fdo::Result<OwnedObjectPath> {
+        self.context.battery_path(name)?;
+        OwnedObjectPath::try_from(battery_object_path(name))
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    fn get_thresholds(&self, battery: &str) -> fdo::Result<(u8, u8)> {
+        let path = self.context.battery_path(battery)?;
+        let backend = self.context.backends.for_battery(path);
+        Thresholds::load(path, backend.as_ref())
+            .map(|t| (t.start, t.end))
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Returns the thresholds the hardware accepted, which firmware may have clamped.
+    async fn set_thresholds(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] server: &ObjectServer,
+        battery: &str,
+        start: u8,
+        end: u8,
+    ) -> fdo::Result<(u8, u8)> {
+        self.context.authorize(&header, connection).await?;
+        let accepted = self.context.save(battery, start, end)?;
+        refresh_battery(server, &self.context, battery).await;
+        Ok((accepted.start, accepted.end))
+    }
+
+    fn list_profiles(&self) -> fdo::Result<Vec<(String, u8, u8)>> {
+        let config = self
+            .context
+            .config
+            .load()
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        Ok(Profile::all(&config)
+            .into_iter()
+            .map(|p| (p.name, p.thresholds.start, p.thresholds.end))
+            .collect())
+    }
+
+    async fn apply_profile(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] server: &ObjectServer,
+        battery: &str,
+        profile: &str,
+    ) -> fdo::Result<(u8, u8)> {
+        self.context.authorize(&header, connection).await?;
+
+        let config = self
+            .context
+            .config
+            .load()
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        let profile = Profile::find(&config, profile)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no profile named {}", profile)))?;
+
+        let accepted =
+            self.context
+                .save(battery, profile.thresholds.start, profile.thresholds.end)?;
+        refresh_battery(server, &self.context, battery).await;
+        Ok((accepted.start, accepted.end))
+    }
+}
+
+/// Live state of one battery, published as properties so clients can follow changes
+/// through `PropertiesChanged` instead of polling.
+#[derive(Clone, Default, PartialEq)]
+struct Snapshot {
+    charge: f64,
+    status: String,
+    /// `None` when the battery doesn't report its design capacity.
+    health: Option<f64>,
+    start: u8,
+    end: u8,
+}
+
+impl Snapshot {
+    fn read(context: &Context, path: &Path) -> Self {
+        let mut snapshot = Self::default();
+
+        if let Ok((battery, _)) = Battery::new(path) {
+            snapshot.charge = battery.charge_percentage() as f64;
+            snapshot.status = battery.status.as_str().to_string();
+            snapshot.health = battery.health_percentage().map(f64::from);
+        }
+        let backend = context.backends.for_battery(path);
+        if let Ok(thresholds) = Thresholds::load(path, backend.as_ref()) {
+            snapshot.start = thresholds.start;
+            snapshot.end = thresholds.end;
+        }
+
+        snapshot
+    }
+}
+
+struct BatteryObject {
+    name: String,
+    snapshot: Snapshot,
+}
+
+#[interface(name = "org.batty.Battery")]
+impl BatteryObject {
+    #[zbus(property)]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[zbus(property)]
+    fn charge(&self) -> f64 {
+        self.snapshot.charge
+    }
+
+    /// charging, discharging, not charging, full or unknown.
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        &self.snapshot.status
+    }
+
+    /// 0 when the battery doesn't report its design capacity; see `HealthKnown`.
+    #[zbus(property)]
+    fn health(&self) -> f64 {
+        self.snapshot.health.unwrap_or(0.0)
+    }
+
+    #[zbus(property)]
+    fn health_known(&self) -> bool {
+        self.snapshot.health.is_some()
+    }
+
+    #[zbus(property)]
+    fn start_threshold(&self) -> u8 {
+        self.snapshot.start
+    }
+
+    #[zbus(property)]
+    fn end_threshold(&self) -> u8 {
+        self.snapshot.end
+    }
+}
+
+/// Re-reads one battery and emits `PropertiesChanged` for the values that differ.
+async fn refresh_battery(server: &ObjectServer, context: &Context, name: &str) {
+    let Ok(path) = context.battery_path(name) else {
+        return;
+    };
+    let object_path = battery_object_path(name);
+    let Ok(iface) = server
+        .interface::<_, BatteryObject>(object_path.as_str())
+        .await
+    else {
+        return;
+    };
+
+    let snapshot = Snapshot::read(context, path);
+    let previous = {
+        let mut object = iface.get_mut().await;
+        std::mem::replace(&mut object.snapshot, snapshot.clone())
+    };
+    if previous == snapshot {
+        return;
+    }
+
+    let object = iface.get().await;
+    let emitter = iface.signal_emitter();
+    let result = emit_changes(&object, emitter, &previous, &snapshot).await;
+    if let Err(e) = result {
+        eprintln!("Failed to emit property changes for {}: {}", name, e);
+    }
+}
+
+async fn emit_changes(
+    object: &BatteryObject,
+    emitter: &SignalEmitter<'_>,
+    previous: &Snapshot,
+    current: &Snapshot,
+) -> zbus::Result<()> {
+    if previous.charge != current.charge {
+        object.charge_changed(emitter).await?;
+    }
+    if previous.status != current.status {
+        object.status_changed(emitter).await?;
+    }
+    if previous.health != current.health {
+        object.health_changed(emitter).await?;
+    }
+    if previous.health.is_some() != current.health.is_some() {
+        object.health_known_changed(emitter).await?;
+    }
+    if previous.start != current.start {
+        object.start_threshold_changed(emitter).await?;
+    }
+    if previous.end != current.end {
+        object.end_threshold_changed(emitter).await?;
+    }
+    Ok(())
+}
+
+/// Serves `org.batty.Manager` and one `org.batty.Battery` object per battery on `bus`,
+/// re-reading the batteries every `interval` until the process is stopped.
+pub fn run(
+    bus: Bus,
+    bat_paths: Vec<PathBuf>,
+    config: ConfigLayers,
+    state_path: PathBuf,
+    backends: Backends,
+    interval: Duration,
+) -> zbus::Result<()> {
+    let builder = match bus {
+        Bus::System => connection::Builder::system()?,
+        Bus::Session => connection::Builder::session()?,
+    };
+    let context = Context {
+        bat_paths,
+        config,
+        state_path,
+        backends,
+        bus,
+    };
+
+    serve(builder, context, interval)
+}
+
+/// Serves the objects on the connection `builder` opens; see [`run`].
+fn serve(
+    builder: connection::Builder<'_>,
+    context: Context,
+    interval: Duration,
+) -> zbus::Result<()> {
+    let context = Arc::new(context);
+    let mut builder = builder.serve_at(
+        MANAGER_PATH,
+        Manager {
+            context: context.clone(),
+        },
+    )?;
+    for path in &context.bat_paths {
+        let name = battery_name(path);
+        builder = builder.serve_at(
+            battery_object_path(name),
+            BatteryObject {
+                name: name.to_string(),
+                snapshot: Snapshot::read(&context, path),
+            },
+        )?;
+    }
+    let connection = builder.name(BUS_NAME)?.build()?;
+
+    loop {
+        thread::sleep(interval);
+        for path in &context.bat_paths {
+            zbus::block_on(refresh_battery(
+                connection.object_server().inner(),
+                &context,
+                battery_name(path),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::state::PendingTopup;
+    use std::{
+        fs,
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::Instant,
+    };
+    use zbus::{
+        blocking::{self, MessageIterator},
+        message::Type,
+    };
+
+    /// A private session bus that is shut down when dropped.
+    pub(crate) struct PrivateBus {
+        daemon: Child,
+        pub(crate) address: String,
+    }
+
+    impl PrivateBus {
+        /// `None` when `dbus-daemon` isn't installed.
+        pub(crate) fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn call<R>(
+        connection: &blocking::Connection,
+        method: &str,
+        body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+    ) -> zbus::Result<R>
+    where
+        R: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+    {
+        connection
+            .call_method(Some(BUS_NAME), MANAGER_PATH, Some(BUS_NAME), method, body)?
+            .body()
+            .deserialize()
+    }
+
+    #[test]
+    fn battery_names_get_distinct_paths() {
+        assert_eq!(battery_object_path("BAT0"), "/org/batty/Battery/BAT0");
+        assert_eq!(battery_object_path("BAT-0"), "/org/batty/Battery/BAT_2d0");
+        assert_eq!(battery_object_path("BAT_0"), "/org/batty/Battery/BAT_5f0");
+    }
+
+    /// Batteries BAT0 and BAT1 at 40-80% under `root`, with a top-up pending for BAT1.
+    fn context(root: &Path, bus: Bus) -> Context {
+        let power_supply = root.join("class/power_supply");
+        let mut bat_paths = Vec::new();
+        for name in ["BAT0", "BAT1"] {
+            let path = power_supply.join(name);
+            fs::create_dir_all(&path).unwrap();
+            for (attr, value) in [
+                ("status", "Discharging"),
+                ("energy_now", "40000000"),
+                ("energy_full", "80000000"),
+                ("charge_control_start_threshold", "40"),
+                ("charge_control_end_threshold", "80"),
+            ] {
+                fs::write(path.join(attr), value).unwrap();
+            }
+            bat_paths.push(path);
+        }
+
+        let state_path = root.join("state.toml");
+        let mut state = State::default();
+        state.topup.insert(
+            "BAT1".to_string(),
+            PendingTopup {
+                previous: Thresholds { start: 40, end: 80 },
+                deadline: u64::MAX,
+            },
+        );
+        state.save(&state_path).unwrap();
+
+        Context {
+            bat_paths,
+            config: ConfigLayers {
+                system: root.join("config.toml"),
+                shared: None,
+                user: None,
+                store: root.join("config.toml"),
+                overrides: Vec::new(),
+            },
+            state_path,
+            backends: Backends::new(&power_supply, None),
+            bus,
+        }
+    }
+
+    fn connect(bus: &PrivateBus) -> blocking::Connection {
+        blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    /// Serves `context` on `bus` and returns a client connection once the daemon answers.
+    fn start_daemon(bus: &PrivateBus, context: Context) -> blocking::Connection {
+        let address = bus.address.clone();
+        thread::spawn(move || {
+            serve(
+                connection::Builder::address(address.as_str())?,
+                context,
+                Duration::from_secs(3600),
+            )
+        });
+
+        let client = connect(bus);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match call::<Vec<String>>(&client, "ListBatteries", &()) {
+                Ok(_) => return client,
+                Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+                Err(e) => panic!("daemon never came up: {}", e),
+            }
+        }
+    }
+
+    /// Stands in for polkit on `bus`, answering every `CheckAuthorization` with `authorized`.
+    fn start_polkit(bus: &PrivateBus, authorized: bool) {
+        let server = connect(bus);
+        server.request_name("org.freedesktop.PolicyKit1").unwrap();
+        let messages = MessageIterator::from(&server);
+
+        thread::spawn(move || {
+            for message in messages.flatten() {
+                let header = message.header();
+                if header.message_type() == Type::MethodCall
+                    && header.member().map(|m| m.as_str()) == Some("CheckAuthorization")
+                {
+                    let details: HashMap<String, String> = HashMap::new();
+                    server
+                        .reply(&header, &(authorized, false, details))
+                        .unwrap();
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn serves_thresholds_on_a_private_bus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+
+        let root = tempfile::tempdir().unwrap();
+        let context = context(root.path(), Bus::Session);
+        let bat_paths = context.bat_paths.clone();
+        let client = start_daemon(&bus, context);
+
+        let batteries: Vec<String> = call(&client, "ListBatteries", &()).unwrap();
+        assert_eq!(batteries, ["BAT0", "BAT1"]);
+        let thresholds: (u8, u8) = call(&client, "GetThresholds", &("BAT0",)).unwrap();
+        assert_eq!(thresholds, (40, 80));
+
+        // Raising start above the current end only works when the pair is checked as a whole
+        let accepted: (u8, u8) = call(&client, "SetThresholds", &("BAT0", 85u8, 95u8)).unwrap();
+        assert_eq!(accepted, (85, 95));
+        assert_eq!(
+            fs::read_to_string(bat_paths[0].join("charge_control_start_threshold")).unwrap(),
+            "85"
+        );
+        let thresholds: (u8, u8) = call(&client, "GetThresholds", &("BAT0",)).unwrap();
+        assert_eq!(thresholds, (85, 95));
+
+        assert!(call::<(u8, u8)>(&client, "SetThresholds", &("BAT0", 90u8, 90u8)).is_err());
+        assert!(call::<(u8, u8)>(&client, "SetThresholds", &("BAT2", 40u8, 80u8)).is_err());
+
+        let accepted: (u8, u8) = call(&client, "ApplyProfile", &("BAT0", "storage")).unwrap();
+        assert_eq!(accepted, (45, 50));
+        assert!(call::<(u8, u8)>(&client, "ApplyProfile", &("BAT0", "missing")).is_err());
+
+        // BAT1 is topping up, so its thresholds must be left for the top-up to restore
+        let err = call::<(u8, u8)>(&client, "SetThresholds", &("BAT1", 50u8, 60u8)).unwrap_err();
+        assert!(err.to_string().contains("top-up is pending"), "{}", err);
+        assert_eq!(
+            fs::read_to_string(bat_paths[1].join("charge_control_end_threshold")).unwrap(),
+            "80"
+        );
+
+        let health_known: bool = client
+            .call_method(
+                Some(BUS_NAME),
+                battery_object_path("BAT0").as_str(),
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &("org.batty.Battery", "HealthKnown"),
+            )
+            .unwrap()
+            .body()
+            .deserialize::<zbus::zvariant::OwnedValue>()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(!health_known);
+    }
+
+    #[test]
+    fn writes_nothing_when_polkit_denies() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        start_polkit(&bus, false);
+
+        let root = tempfile::tempdir().unwrap();
+        let context = context(root.path(), Bus::System);
+        let bat_paths = context.bat_paths.clone();
+        let client = start_daemon(&bus, context);
+
+        for (method, result) in [
+            (
+                "SetThresholds",
+                call::<(u8, u8)>(&client, "SetThresholds", &("BAT0", 85u8, 95u8)),
+            ),
+            (
+                "ApplyProfile",
+                call::<(u8, u8)>(&client, "ApplyProfile", &("BAT0", "storage")),
+            ),
+        ] {
+            let err = result.unwrap_err();
+            assert!(
+                err.to_string().contains("not authorized"),
+                "{}: {}",
+                method,
+                err
+            );
+        }
+
+        let thresholds: (u8, u8) = call(&client, "GetThresholds", &("BAT0",)).unwrap();
+        assert_eq!(thresholds, (40, 80));
+        assert_eq!(
+            fs::read_to_string(bat_paths[0].join("charge_control_start_threshold")).unwrap(),
+            "40"
+        );
+        assert!(!root.path().join("config.toml").exists());
+        assert!(State::load(&root.path().join("state.toml"))
+            .unwrap()
+            .applied
+            .is_empty());
+    }
+}
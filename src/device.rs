@@ -0,0 +1,84 @@
+use crate::battery::{
+    battery_name, find_batteries, read_str_battery_attribute, Battery, BatteryAttribute,
+    BatteryScope, BatteryStatus,
+};
+use serde::Serialize;
+use std::{io, path::Path};
+
+/// A peripheral battery (mouse, keyboard, controller, headset). Devices are read-only:
+/// they have no charge thresholds.
+#[derive(Serialize)]
+pub struct Device {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity_percent: Option<f32>,
+    /// Coarse level such as `Low`, `Normal` or `Full`, for devices that report no percentage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity_level: Option<String>,
+    pub status: BatteryStatus,
+}
+
+impl Device {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let attribute = |attr| {
+            read_str_battery_attribute(path, attr)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty() && value != "Unknown")
+        };
+
+        // Devices that report a capacity go through the regular battery code; the rest
+        // only have a status and a coarse level.
+        let (capacity_percent, status, battery_error) = match Battery::new(path) {
+            Ok((battery, _)) => (Some(battery.charge_percentage()), battery.status, None),
+            Err(e) => (
+                None,
+                attribute(BatteryAttribute::Status)
+                    .and_then(|status| BatteryStatus::parse(&status))
+                    .unwrap_or(BatteryStatus::Unknown),
+                Some(e),
+            ),
+        };
+        let capacity_level = attribute(BatteryAttribute::CapacityLevel);
+
+        if let (None, None, Some(e)) = (capacity_percent, &capacity_level, battery_error) {
+            return Err(e);
+        }
+
+        Ok(Self {
+            name: battery_name(path).to_string(),
+            model_name: attribute(BatteryAttribute::ModelName),
+            manufacturer: attribute(BatteryAttribute::Manufacturer),
+            capacity_percent,
+            capacity_level,
+            status,
+        })
+    }
+
+    /// The model name when the driver reports one, otherwise the sysfs name.
+    pub fn display_name(&self) -> &str {
+        self.model_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The charge as a percentage, or the coarse level when that's all there is.
+    pub fn level(&self) -> String {
+        match (self.capacity_percent, &self.capacity_level) {
+            (Some(percent), _) => format!("{:.0}%", percent),
+            (None, Some(level)) => level.clone(),
+            (None, None) => "--".to_string(),
+        }
+    }
+}
+
+/// Reads every peripheral battery, sorted by sysfs name. Devices that can't be read,
+/// typically because they disconnected mid-scan, are skipped.
+pub fn find_devices(power_supply_path: &Path) -> Vec<Device> {
+    find_batteries(power_supply_path, BatteryScope::Device)
+        .iter()
+        .filter_map(|path| Device::new(path).ok())
+        .collect()
+}
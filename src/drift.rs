@@ -0,0 +1,43 @@
+use crate::{
+    backend::ThresholdBackend, battery::battery_name, state::State, thresholds::Thresholds,
+};
+use std::{fmt, io, path::Path};
+
+/// Thresholds that differ from the ones batty last applied, typically because a BIOS
+/// update or another operating system reset them.
+pub struct Drift {
+    pub expected: Thresholds,
+    pub actual: Thresholds,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thresholds are {}%-{}%, batty applied {}%-{}%",
+            self.actual.start, self.actual.end, self.expected.start, self.expected.end
+        )
+    }
+}
+
+/// Compares the thresholds of `battery_path` with the ones recorded in `state`. Returns
+/// `None` when they match or batty never applied thresholds to the battery.
+pub fn detect(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state: &State,
+) -> io::Result<Option<Drift>> {
+    let Some(expected) = state.applied.get(battery_name(battery_path)) else {
+        return Ok(None);
+    };
+
+    let actual = Thresholds::load(battery_path, backend)?;
+    if &actual == expected {
+        return Ok(None);
+    }
+
+    Ok(Some(Drift {
+        expected: expected.clone(),
+        actual,
+    }))
+}
@@ -0,0 +1,578 @@
+use crate::{
+    backend::Backends,
+    battery::{battery_name, Battery, BatteryStatus, CapacityUnit},
+    state,
+    thresholds::Thresholds,
+};
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_HISTORY_DIR: &str = "/var/lib/batty/history";
+pub const DEFAULT_KEEP_MONTHS: u32 = 24;
+
+const HEADER: &str =
+    "timestamp,battery,status,energy_now,energy_full,energy_full_design,cycles,power,start,end,unit";
+
+/// One reading of one battery. Capacities are in the battery's own `unit` (µWh, µAh or
+/// percent), power in µW.
+#[derive(Clone)]
+pub struct Sample {
+    pub timestamp: u64,
+    pub battery: String,
+    pub status: String,
+    pub energy_now: u32,
+    pub energy_full: u32,
+    pub energy_full_design: Option<u32>,
+    pub cycles: Option<u32>,
+    pub power: Option<u32>,
+    pub thresholds: Option<Thresholds>,
+    /// `None` for rows written before the unit was recorded.
+    pub unit: Option<CapacityUnit>,
+}
+
+impl Sample {
+    pub fn read(path: &Path, backends: &Backends, timestamp: u64) -> io::Result<Self> {
+        let (battery, _) = Battery::new(path)?;
+        let thresholds = Thresholds::load(path, backends.for_battery(path).as_ref()).ok();
+
+        Ok(Self {
+            timestamp,
+            battery: battery_name(path).to_string(),
+            status: battery.status.as_str().to_string(),
+            energy_now: battery.curr_power,
+            energy_full: battery.total_power,
+            energy_full_design: battery.design_power,
+            cycles: battery.cycles,
+            power: battery.power_draw,
+            thresholds,
+            unit: Some(battery.unit),
+        })
+    }
+
+    pub fn charge_percentage(&self) -> f32 {
+        (self.energy_now as f32 / self.energy_full as f32) * 100.0
+    }
+
+    pub fn health_percentage(&self) -> Option<f32> {
+        self.energy_full_design
+            .filter(|design| *design > 0)
+            .map(|design| (self.energy_full as f32 / design as f32) * 100.0)
+    }
+
+    fn to_csv(&self) -> String {
+        let opt = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+        let (start, end) = match &self.thresholds {
+            Some(t) => (t.start.to_string(), t.end.to_string()),
+            None => (String::new(), String::new()),
+        };
+
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.battery,
+            self.status,
+            self.energy_now,
+            self.energy_full,
+            opt(self.energy_full_design),
+            opt(self.cycles),
+            opt(self.power),
+            start,
+            end,
+            self.unit.map(|unit| unit.as_str()).unwrap_or_default()
+        )
+    }
+
+    fn from_csv(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(',').collect();
+        // Older files have no unit column
+        if fields.len() != 10 && fields.len() != 11 {
+            return None;
+        }
+
+        let opt = |field: &str| field.parse::<u32>().ok();
+        let thresholds = match (fields[8].parse::<u8>(), fields[9].parse::<u8>()) {
+            (Ok(start), Ok(end)) => Some(Thresholds { start, end }),
+            _ => None,
+        };
+
+        Some(Self {
+            timestamp: fields[0].parse().ok()?,
+            battery: fields[1].to_string(),
+            status: fields[2].to_string(),
+            energy_now: fields[3].parse().ok()?,
+            energy_full: fields[4].parse().ok()?,
+            energy_full_design: opt(fields[5]),
+            cycles: opt(fields[6]),
+            power: opt(fields[7]),
+            thresholds,
+            unit: fields.get(10).and_then(|unit| CapacityUnit::parse(unit)),
+        })
+    }
+}
+
+/// Append-only CSV store with one file per month, e.g. `2026-10.csv`. Rotation deletes
+/// whole months, so queries only need to open the files covering their range.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn append(&self, samples: &[Sample]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        for sample in samples {
+            let path = self.file_for(sample.timestamp);
+            let is_new = !path.exists();
+
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            if is_new {
+                writeln!(file, "{}", HEADER)?;
+            }
+            writeln!(file, "{}", sample.to_csv())?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes monthly files older than the last `keep_months` months.
+    pub fn rotate(&self, now: u64, keep_months: u32) -> io::Result<()> {
+        let (year, month, _) = civil_from_unix(now);
+        let current = year * 12 + month as i64 - 1;
+
+        for (path, year, month) in self.files()? {
+            if current - (year * 12 + month as i64 - 1) >= keep_months as i64 {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Samples with `since <= timestamp < until`, optionally for one battery only.
+    pub fn query(&self, battery: Option<&str>, since: u64, until: u64) -> io::Result<Vec<Sample>> {
+        let (first_year, first_month, _) = civil_from_unix(since);
+        let (last_year, last_month, _) = civil_from_unix(until.saturating_sub(1));
+        let first = first_year * 12 + first_month as i64;
+        let last = last_year * 12 + last_month as i64;
+
+        let mut samples = Vec::new();
+
+        for (path, year, month) in self.files()? {
+            let index = year * 12 + month as i64;
+            if index < first || index > last {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&path)?;
+            samples.extend(
+                contents
+                    .lines()
+                    .skip(1)
+                    .filter_map(Sample::from_csv)
+                    .filter(|s| s.timestamp >= since && s.timestamp < until)
+                    .filter(|s| battery.is_none_or(|name| s.battery == name)),
+            );
+        }
+
+        samples.sort_by_key(|s| s.timestamp);
+        Ok(samples)
+    }
+
+    fn file_for(&self, timestamp: u64) -> PathBuf {
+        let (year, month, _) = civil_from_unix(timestamp);
+        self.dir.join(format!("{:04}-{:02}.csv", year, month))
+    }
+
+    fn files(&self) -> io::Result<Vec<(PathBuf, i64, u32)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut files: Vec<_> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_suffix(".csv")?
+                    .to_string();
+                let (year, month) = name.split_once('-')?;
+                Some((entry.path(), year.parse().ok()?, month.parse().ok()?))
+            })
+            .collect();
+
+        files.sort_by_key(|(_, year, month)| (*year, *month));
+        Ok(files)
+    }
+}
+
+pub fn history_dir(path: Option<PathBuf>) -> PathBuf {
+    path.unwrap_or_else(|| PathBuf::from(DEFAULT_HISTORY_DIR))
+}
+
+/// Samples every battery every `interval` seconds until the process is stopped.
+pub fn run_logger(
+    history: &History,
+    bat_paths: &[PathBuf],
+    backends: &Backends,
+    interval: u64,
+    keep_months: u32,
+) -> io::Result<()> {
+    loop {
+        let now = state::now();
+        let mut samples = Vec::new();
+
+        for path in bat_paths {
+            match Sample::read(path, backends, now) {
+                Ok(sample) => samples.push(sample),
+                Err(e) => eprintln!("Failed to sample {}: {}", battery_name(path), e),
+            }
+        }
+
+        history.append(&samples)?;
+        history.rotate(now, keep_months)?;
+
+        std::thread::sleep(std::time::Duration::from_secs(interval));
+    }
+}
+
+/// Averages `value` over consecutive `bucket`-second windows, returning each window's
+/// start and mean. Samples without a value are skipped.
+pub fn bucket_means<F>(samples: &[Sample], bucket: u64, value: F) -> Vec<(u64, f32)>
+where
+    F: Fn(&Sample) -> Option<f32>,
+{
+    let mut means: Vec<(u64, f32, u32)> = Vec::new();
+
+    for sample in samples {
+        let Some(v) = value(sample) else {
+            continue;
+        };
+        let start = sample.timestamp - sample.timestamp % bucket;
+
+        match means.last_mut() {
+            Some((last, sum, count)) if *last == start => {
+                *sum += v;
+                *count += 1;
+            }
+            _ => means.push((start, v, 1)),
+        }
+    }
+
+    means
+        .into_iter()
+        .map(|(start, sum, count)| (start, sum / count as f32))
+        .collect()
+}
+
+pub struct Summary {
+    pub samples: usize,
+    pub first: u64,
+    pub last: u64,
+    pub charge_min: f32,
+    pub charge_max: f32,
+    pub charge_avg: f32,
+    pub health_first: Option<f32>,
+    pub health_last: Option<f32>,
+    pub energy_full_first: u32,
+    pub energy_full_last: u32,
+    pub unit: Option<CapacityUnit>,
+    pub cycles_first: Option<u32>,
+    pub cycles_last: Option<u32>,
+    /// Mean power over samples taken while discharging, in µW.
+    pub discharge_power_avg: Option<u32>,
+}
+
+impl Summary {
+    pub fn new(samples: &[Sample]) -> Option<Self> {
+        let first = samples.first()?;
+        let last = samples.last()?;
+
+        let charges: Vec<f32> = samples.iter().map(Sample::charge_percentage).collect();
+        let discharge_power: Vec<u64> = samples
+            .iter()
+            .filter(|s| BatteryStatus::parse(&s.status) == Some(BatteryStatus::Discharging))
+            .filter_map(|s| s.power.map(u64::from))
+            .collect();
+
+        Some(Self {
+            samples: samples.len(),
+            first: first.timestamp,
+            last: last.timestamp,
+            charge_min: charges.iter().copied().fold(f32::MAX, f32::min),
+            charge_max: charges.iter().copied().fold(f32::MIN, f32::max),
+            charge_avg: charges.iter().sum::<f32>() / charges.len() as f32,
+            health_first: first.health_percentage(),
+            health_last: last.health_percentage(),
+            energy_full_first: first.energy_full,
+            energy_full_last: last.energy_full,
+            unit: last.unit,
+            cycles_first: first.cycles,
+            cycles_last: last.cycles,
+            discharge_power_avg: (!discharge_power.is_empty()).then(|| {
+                (discharge_power.iter().sum::<u64>() / discharge_power.len() as u64) as u32
+            }),
+        })
+    }
+
+    pub fn render(&self, battery: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}:", battery);
+        let _ = writeln!(
+            out,
+            "  Range:   {} to {} ({} samples)",
+            format_timestamp(self.first),
+            format_timestamp(self.last),
+            self.samples
+        );
+        let _ = writeln!(
+            out,
+            "  Charge:  min {:.1}%, max {:.1}%, avg {:.1}%",
+            self.charge_min, self.charge_max, self.charge_avg
+        );
+        if let (Some(first), Some(last)) = (self.health_first, self.health_last) {
+            let _ = writeln!(
+                out,
+                "  Health:  {:.1}% -> {:.1}% ({:+.1})",
+                first,
+                last,
+                last - first
+            );
+        }
+        let unit = self
+            .unit
+            .map(|unit| format!(" {}", unit.as_str()))
+            .unwrap_or_default();
+        let change = if self.energy_full_first > 0 {
+            format!(
+                " ({:+.1}%)",
+                (self.energy_full_last as f32 / self.energy_full_first as f32 - 1.0) * 100.0
+            )
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            out,
+            "  Full:    {} -> {}{}{}",
+            self.energy_full_first, self.energy_full_last, unit, change
+        );
+        if let (Some(first), Some(last)) = (self.cycles_first, self.cycles_last) {
+            let _ = writeln!(out, "  Cycles:  {} -> {}", first, last);
+        }
+        if let Some(power) = self.discharge_power_avg {
+            let _ = writeln!(
+                out,
+                "  Draw:    {:.2} W average while discharging",
+                power as f32 / 1_000_000.0
+            );
+        }
+        out
+    }
+}
+
+/// Parses a range bound: a relative age like `30m`, `12h` or `7d`, a `YYYY-MM-DD` date
+/// (UTC midnight) or a Unix timestamp.
+pub fn parse_time(value: &str, now: u64) -> Result<u64, String> {
+    let invalid = || format!("invalid time: {} (use e.g. 12h, 7d, 2026-01-31)", value);
+
+    if let Some((number, unit)) = value
+        .char_indices()
+        .last()
+        .filter(|(_, c)| c.is_ascii_alphabetic())
+        .map(|(i, c)| (&value[..i], c))
+    {
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let age = number.checked_mul(seconds).ok_or_else(invalid)?;
+        return Ok(now.saturating_sub(age));
+    }
+
+    if let [year, month, day] = value.split('-').collect::<Vec<_>>()[..] {
+        let year: i64 = year.parse().map_err(|_| invalid())?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        let day: u32 = day.parse().map_err(|_| invalid())?;
+        if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day)
+        {
+            return Err(invalid());
+        }
+        let days = days_from_civil(year, month, day);
+        return u64::try_from(days * 24 * 60 * 60).map_err(|_| invalid());
+    }
+
+    value.parse().map_err(|_| invalid())
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_unix(timestamp);
+    let seconds = timestamp % (24 * 60 * 60);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds % 3600) / 60
+    )
+}
+
+fn civil_from_unix(timestamp: u64) -> (i64, u32, u32) {
+    civil_from_days((timestamp / (24 * 60 * 60)) as i64)
+}
+
+// Date conversions follow Howard Hinnant's civil_from_days/days_from_civil algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        let dates = [
+            (1970, 1, 1),
+            (1999, 12, 31),
+            (2000, 1, 1),
+            (2000, 2, 29),
+            (2000, 3, 1),
+            (2024, 2, 29),
+            (2025, 2, 28),
+            (2025, 3, 1),
+            (2026, 1, 31),
+            (2026, 2, 1),
+            (2026, 12, 31),
+            (2027, 1, 1),
+            (2100, 2, 28),
+            (2100, 3, 1),
+        ];
+
+        for (year, month, day) in dates {
+            let days = days_from_civil(year, month, day);
+            assert_eq!(civil_from_days(days), (year, month, day));
+        }
+
+        for pair in dates.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if days_from_civil(b.0, b.1, b.2) - days_from_civil(a.0, a.1, a.2) == 1 {
+                assert_eq!(civil_from_days(days_from_civil(a.0, a.1, a.2) + 1), b);
+            }
+        }
+    }
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_unix(1_709_164_800), (2024, 2, 29));
+        assert_eq!(civil_from_unix(1_709_251_199), (2024, 2, 29));
+        assert_eq!(civil_from_unix(1_709_251_200), (2024, 3, 1));
+        assert_eq!(format_timestamp(1_735_689_599), "2024-12-31 23:59");
+        assert_eq!(format_timestamp(1_735_689_600), "2025-01-01 00:00");
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = 1_000 * DAY;
+        assert_eq!(parse_time("30m", now), Ok(now - 30 * 60));
+        assert_eq!(parse_time("7d", now), Ok(now - 7 * DAY));
+        assert_eq!(parse_time("2w", now), Ok(now - 14 * DAY));
+        assert_eq!(parse_time("99999w", now), Ok(0));
+    }
+
+    #[test]
+    fn rejects_overflowing_ages() {
+        assert!(parse_time("99999999999999999w", 0).is_err());
+        assert!(parse_time("18446744073709551615s", 0).is_ok());
+        assert!(parse_time("18446744073709551615m", 0).is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_time("2024-02-29", 0), Ok(1_709_164_800));
+        assert_eq!(parse_time("1700000000", 0), Ok(1_700_000_000));
+        assert!(parse_time("2024-13-01", 0).is_err());
+        assert!(parse_time("99999999999999999-01-01", 0).is_err());
+        assert!(parse_time("1969-12-31", 0).is_err());
+        assert!(parse_time("7y", 0).is_err());
+    }
+
+    fn sample(energy_full: u32, unit: Option<CapacityUnit>) -> Sample {
+        Sample {
+            timestamp: 1_700_000_000,
+            battery: "BAT0".to_string(),
+            status: "Discharging".to_string(),
+            energy_now: energy_full / 2,
+            energy_full,
+            energy_full_design: Some(60_000_000),
+            cycles: Some(42),
+            power: None,
+            thresholds: Some(Thresholds { start: 40, end: 80 }),
+            unit,
+        }
+    }
+
+    #[test]
+    fn csv_round_trip_keeps_unit() {
+        let line = sample(50_000_000, Some(CapacityUnit::Charge)).to_csv();
+        assert!(line.ends_with(",40,80,µAh"));
+
+        let parsed = Sample::from_csv(&line).unwrap();
+        assert_eq!(parsed.unit, Some(CapacityUnit::Charge));
+        assert_eq!(parsed.energy_full, 50_000_000);
+        assert_eq!(parsed.thresholds, Some(Thresholds { start: 40, end: 80 }));
+    }
+
+    #[test]
+    fn reads_rows_without_unit() {
+        let parsed = Sample::from_csv("1700000000,BAT0,Full,50,100,,,,40,80").unwrap();
+        assert_eq!(parsed.unit, None);
+        assert_eq!(parsed.energy_now, 50);
+    }
+
+    #[test]
+    fn summary_handles_zero_full_capacity() {
+        let summary = Summary::new(&[sample(0, None)]).unwrap();
+        let rendered = summary.render("BAT0");
+        assert!(rendered.contains("Full:    0 -> 0\n"), "{}", rendered);
+    }
+}
@@ -1,77 +1,1008 @@
+mod backend;
 mod battery;
+mod calibrate;
 mod cli;
+mod config;
+mod daemon;
+mod device;
+mod drift;
+mod history;
+mod power_source;
+mod profile;
+mod report;
+mod service;
+mod state;
 mod thresholds;
+mod topup;
 mod tui;
+mod watch;
 
-use battery::find_batteries;
-use clap::Parser;
-use cli::Cli;
-use std::path::PathBuf;
-use thresholds::{ThresholdKind, Thresholds};
+use backend::{Backends, ThresholdBackend};
+use battery::{battery_name, find_batteries, Battery, BatteryScope, ChargeBehaviour};
+use clap::{Parser, ValueEnum};
+use cli::{Cli, Command, ConfigAction, ProfileAction};
+use config::{Config, ConfigLayers, Override, Source};
+use history::{History, Summary};
+use profile::Profile;
+use report::{OutputFormat, Report};
+use service::{DaemonFiles, PermissionFiles, ServiceFiles, WritableDevice};
+use state::State;
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use thresholds::{SaveReport, ThresholdKind, Thresholds};
+
+const TOPUP_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const CALIBRATE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const SHOW_CONFIG_WIDTH: usize = 48;
 
 fn main() {
     let cli = Cli::parse();
 
-    let power_supply_path = cli
-        .path
-        .unwrap_or_else(|| PathBuf::from("/sys/class/power_supply"));
+    let config_path = config::config_path(cli.config.clone());
+    let state_path = state::state_path(cli.state.clone());
+    let history_dir = history::history_dir(cli.history_dir.clone());
+
+    let layers = ConfigLayers::new(
+        config_path.clone(),
+        config::store_path(cli.config.clone()),
+        cli_overrides(&cli),
+    );
+
+    // Handled before loading the config so a broken one can still be inspected and fixed
+    if let Some(Command::Config { ref action }) = cli.command {
+        match action {
+            ConfigAction::Show => show_config(&layers),
+            ConfigAction::Validate => validate_config(&layers),
+            ConfigAction::Edit { system } => edit_config(&layers, *system),
+        }
+        return;
+    }
+
+    let config = match layers.load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let power_supply_path = config.power_supply_path();
+    let backends = Backends::new(&power_supply_path, config.backend);
+
+    let command = match cli.command.clone() {
+        Some(command) => command,
+        None => legacy_command(&cli),
+    };
+
+    match command {
+        Command::Apply => {
+            apply(
+                &config,
+                &config_path,
+                &state_path,
+                &power_supply_path,
+                &backends,
+            );
+        }
+        Command::InstallService { dry_run, daemon } => {
+            install_service(&cli, dry_run, daemon);
+        }
+        Command::SetupPermissions {
+            ref group,
+            dry_run,
+            uninstall,
+        } => {
+            setup_permissions(&power_supply_path, &backends, group, dry_run, uninstall);
+        }
+        Command::Topup {
+            timeout,
+            no_wait,
+            restore,
+        } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, cli.all);
+            let timeout = Duration::from_secs(timeout * 60 * 60);
+
+            if restore {
+                restore_topup(&targets, &backends, &state_path);
+            } else {
+                start_topup(&targets, &backends, &state_path, timeout, no_wait);
+            }
+        }
+        Command::Calibrate { abort } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, cli.all);
+
+            if abort {
+                abort_calibration(&targets, &backends, &state_path);
+            } else {
+                calibrate(&targets, &backends, &state_path);
+            }
+        }
+        Command::Log {
+            interval,
+            keep_months,
+        } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, true);
+            let history = History::new(history_dir.clone());
 
-    let bat_paths = find_batteries(&power_supply_path);
+            println!(
+                "Logging {} batter{} to {} every {}s",
+                targets.len(),
+                if targets.len() == 1 { "y" } else { "ies" },
+                history_dir.display(),
+                interval
+            );
+
+            if let Err(e) =
+                history::run_logger(&history, &targets, &backends, interval.max(1), keep_months)
+            {
+                eprintln!("Failed to write history: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Watch {
+            interval,
+            low,
+            critical,
+            health_below,
+            ref event,
+            ref sink,
+            ref hook,
+            reapply,
+            once,
+        } => {
+            let mut watch = config.watch.clone().unwrap_or_default();
+            watch.interval = interval.unwrap_or(watch.interval).max(1);
+            watch.low = low.unwrap_or(watch.low);
+            watch.critical = critical.unwrap_or(watch.critical);
+            watch.health_below = health_below.unwrap_or(watch.health_below);
+            if !event.is_empty() {
+                watch.events = event.clone();
+            }
+            if !sink.is_empty() {
+                watch.sinks = sink.clone();
+            }
+            watch.reapply |= reapply;
+            if let Some(hook) = hook {
+                watch.hook = Some(hook.clone());
+                if !watch.sinks.contains(&watch::SinkKind::Hook) {
+                    watch.sinks.push(watch::SinkKind::Hook);
+                }
+            }
+
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, true);
+
+            if let Err(e) = watch::run(&watch, &targets, &backends, &state_path, once) {
+                eprintln!("Failed to watch batteries: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Daemon { session, interval } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let bus = if session {
+                daemon::Bus::Session
+            } else {
+                daemon::Bus::System
+            };
+
+            if let Err(e) = daemon::run(
+                bus,
+                bat_paths,
+                layers,
+                state_path,
+                backends,
+                Duration::from_secs(interval.max(1)),
+            ) {
+                eprintln!("Failed to run the D-Bus service: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Check => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, true);
+            check(&targets, &backends, &state_path);
+        }
+        Command::History {
+            ref since,
+            ref until,
+            stats,
+        } => {
+            show_history(&cli.battery, since, until.as_deref(), &history_dir, stats);
+        }
+        Command::Devices { format } => {
+            show_devices(&power_supply_path, format);
+        }
+        Command::Config { .. } => unreachable!(),
+        Command::Profile { ref action } => {
+            profile(
+                &cli,
+                action,
+                &config,
+                &layers,
+                &state_path,
+                &power_supply_path,
+                &backends,
+            );
+        }
+        Command::Status { format } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            status(&cli, format, &bat_paths, &power_supply_path, &backends);
+        }
+        Command::Get { kind } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, cli.all);
+            get_thresholds(&targets, &backends, kind);
+        }
+        Command::Set {
+            range,
+            start,
+            end,
+            charge_behaviour,
+        } => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, cli.all);
+            let (start, end) = match range {
+                Some(range) => (Some(range.start), Some(range.end)),
+                None => (start, end),
+            };
+
+            if let Some(behaviour) = charge_behaviour {
+                set_charge_behaviour(&targets, behaviour);
+            }
+            if start.is_some() || end.is_some() {
+                set_thresholds(&cli, &targets, &backends, &layers, &state_path, start, end);
+            }
+        }
+        Command::Tui => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            let bat_paths = select_batteries(&bat_paths, &cli.battery, true);
+
+            if let Err(err) = tui::run_tui(
+                bat_paths,
+                cli.devices,
+                &config,
+                layers.store.clone(),
+                state_path,
+                history_dir,
+                backends,
+            ) {
+                eprintln!("Failed to run TUI: {}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::List => {
+            let bat_paths = discover_batteries(&power_supply_path);
+            list_batteries(&bat_paths, &backends);
+        }
+    }
+}
+
+/// Resolves `--battery`/`--all` against the discovered batteries, exiting on unknown names.
+/// With neither given, `all` decides between every battery and just the first one.
+fn select_batteries(bat_paths: &[PathBuf], names: &[String], all: bool) -> Vec<PathBuf> {
+    if names.is_empty() {
+        return if all {
+            bat_paths.to_vec()
+        } else {
+            bat_paths[..1].to_vec()
+        };
+    }
+
+    let mut selected = Vec::new();
+
+    for name in names {
+        match bat_paths.iter().find(|p| battery_name(p) == name) {
+            Some(path) if !selected.contains(path) => selected.push(path.clone()),
+            Some(_) => {}
+            None => {
+                let available: Vec<&str> = bat_paths.iter().map(|p| battery_name(p)).collect();
+                eprintln!(
+                    "Error: battery {} not found (available: {})",
+                    name,
+                    available.join(", ")
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    selected
+}
+
+fn discover_batteries(power_supply_path: &Path) -> Vec<PathBuf> {
+    let bat_paths = find_batteries(power_supply_path, BatteryScope::System);
 
     if bat_paths.is_empty() {
-        eprintln!("Error: No batteries found in {}", power_supply_path.display());
+        eprintln!(
+            "Error: No batteries found in {}",
+            power_supply_path.display()
+        );
         eprintln!("Make sure you're running on a laptop with battery support.");
         std::process::exit(1);
     }
 
-    if cli.tui {
-        if cli.value.is_some() {
-            eprintln!("Error: --value cannot be used with --tui");
+    bat_paths
+}
+
+/// Computes new thresholds for every target with `update` and saves them. All batteries are
+/// validated before anything is written and a failed write rolls back the batteries already
+/// updated, unless `keep_going` asks for a partial update. `describe` builds the success line.
+fn update_thresholds<U, D>(
+    targets: &[PathBuf],
+    backends: &Backends,
+    keep_going: bool,
+    layers: &ConfigLayers,
+    state_path: &Path,
+    update: U,
+    describe: D,
+) where
+    U: Fn(&Thresholds) -> Result<Thresholds, String>,
+    D: Fn(&Path, &SaveReport) -> String,
+{
+    let single = targets.len() == 1;
+    let prefix = |path: &Path| battery_prefix(path, single);
+
+    let state = match State::load(state_path) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to load state: {}", e);
             std::process::exit(1);
         }
+    };
 
-        if let Err(err) = tui::run_tui(bat_paths) {
-            eprintln!("Failed to run TUI: {}", err);
-            std::process::exit(1);
+    let mut planned = Vec::new();
+    let mut failed = false;
+
+    for path in targets {
+        // A pending top-up or calibration would later overwrite the new thresholds
+        if let Err(e) = state.ensure_idle(battery_name(path)) {
+            eprintln!("{}Error: {}", prefix(path), e);
+            failed = true;
+            continue;
         }
 
-        return;
+        let backend = backends.for_battery(path);
+        let original = match Thresholds::load(path, backend.as_ref()) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("{}Failed to load current thresholds: {}", prefix(path), e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let thresholds = match update(&original) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("{}Error: {}", prefix(path), e);
+                failed = true;
+                continue;
+            }
+        };
+
+        planned.push((path, backend, original, thresholds));
     }
 
-    // Use the first battery for CLI operations
-    let battery_path = &bat_paths[0];
+    if failed && !keep_going {
+        if !single {
+            eprintln!(
+                "No thresholds were changed. Pass --keep-going to update the remaining batteries."
+            );
+        }
+        std::process::exit(1);
+    }
+
+    let mut written: Vec<(&PathBuf, Box<dyn ThresholdBackend>, Thresholds, Thresholds)> =
+        Vec::new();
+
+    for (path, backend, original, thresholds) in planned {
+        let report = match thresholds.save(path, backend.as_ref()) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("{}Failed to save thresholds: {}", prefix(path), e);
+                failed = true;
+
+                if keep_going {
+                    continue;
+                }
 
-    if let Some(value) = cli.value {
-        let kind = match cli.kind.to_lowercase().as_str() {
-            "start" => ThresholdKind::Start,
-            "end" => ThresholdKind::End,
-            _ => {
-                eprintln!("Error: kind must be either 'start' or 'end'");
+                for (path, backend, original, _) in written.iter().rev() {
+                    match original.save(path, backend.as_ref()) {
+                        Ok(report) => {
+                            eprintln!("{}Restored previous thresholds", prefix(path));
+                            record_applied(state_path, path, &report.accepted);
+                        }
+                        Err(e) => eprintln!("{}Failed to restore thresholds: {}", prefix(path), e),
+                    }
+                }
                 std::process::exit(1);
             }
         };
 
-        let mut thresholds = match Thresholds::load(battery_path) {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("Failed to load current thresholds: {}", e);
+        println!("{}", describe(path, &report));
+        for message in report.clamp_messages() {
+            eprintln!("{}Warning: firmware {}", prefix(path), message);
+        }
+
+        record_applied(state_path, path, &report.accepted);
+
+        written.push((path, backend, original, report.accepted));
+    }
+
+    // Only store thresholds once every battery took them, so a rolled-back write is never
+    // reapplied by `batty apply` at boot.
+    for (path, _, _, accepted) in &written {
+        if let Err(e) = config::persist_thresholds(&layers.store, battery_name(path), accepted) {
+            eprintln!(
+                "{}Warning: {}",
+                prefix(path),
+                config::store_error(&layers.store, &e)
+            );
+            continue;
+        }
+        warn_overridden(layers, &format!("batteries.{}", battery_name(path)));
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Warns when a layer above `layers.store` replaces what was just stored there under `key`,
+/// since the merged config, not the stored value, is what later commands use.
+fn warn_overridden(layers: &ConfigLayers, key: &str) {
+    let sources = match layers.overriding(key) {
+        Ok(sources) if !sources.is_empty() => sources,
+        _ => return,
+    };
+    let sources: Vec<String> = sources.iter().map(|source| source.to_string()).collect();
+
+    eprintln!(
+        "Warning: {} stored in {} is overridden by {}",
+        key,
+        layers.store.display(),
+        sources.join(", ")
+    );
+}
+
+/// Remembers thresholds batty wrote so `batty check` can tell when firmware overrides them.
+/// Failing to do so only costs drift detection, so it is reported as a warning.
+fn record_applied(state_path: &Path, battery_path: &Path, thresholds: &Thresholds) {
+    if let Err(e) = state::record_applied(state_path, battery_name(battery_path), thresholds) {
+        eprintln!(
+            "Warning: failed to record applied thresholds in {}: {}",
+            state_path.display(),
+            e
+        );
+    }
+}
+
+fn profile(
+    cli: &Cli,
+    action: &ProfileAction,
+    config: &Config,
+    layers: &ConfigLayers,
+    state_path: &Path,
+    power_supply_path: &Path,
+    backends: &Backends,
+) {
+    let find = |config: &Config, name: &str| {
+        Profile::find(config, name).unwrap_or_else(|| {
+            eprintln!("Error: profile {} not found", name);
+            std::process::exit(1);
+        })
+    };
+
+    match action {
+        ProfileAction::List => {
+            for profile in Profile::all(config) {
+                println!(
+                    "{:<12} {}%-{}%{}",
+                    profile.name,
+                    profile.thresholds.start,
+                    profile.thresholds.end,
+                    if profile.builtin { " (built-in)" } else { "" }
+                );
+            }
+        }
+        ProfileAction::Show { name } => {
+            let profile = find(config, name);
+            println!("Profile {}:", profile.name);
+            println!("  Start: {}%", profile.thresholds.start);
+            println!("  End:   {}%", profile.thresholds.end);
+        }
+        ProfileAction::Apply { name } => {
+            let profile = find(config, name);
+            let bat_paths = discover_batteries(power_supply_path);
+            let targets = select_batteries(&bat_paths, &cli.battery, cli.all);
+            let single = targets.len() == 1;
+
+            update_thresholds(
+                &targets,
+                backends,
+                cli.keep_going,
+                layers,
+                state_path,
+                |_| {
+                    profile.thresholds.validate()?;
+                    Ok(profile.thresholds.clone())
+                },
+                |path, report| {
+                    format!(
+                        "{}Applied profile {}: thresholds set to {}%-{}%",
+                        battery_prefix(path, single),
+                        profile.name,
+                        report.accepted.start,
+                        report.accepted.end
+                    )
+                },
+            );
+        }
+        ProfileAction::Save { name, start, end } => {
+            let thresholds = match (start, end) {
+                (Some(start), Some(end)) => Thresholds {
+                    start: *start,
+                    end: *end,
+                },
+                (None, None) => {
+                    let bat_paths = discover_batteries(power_supply_path);
+                    let path = &select_batteries(&bat_paths, &cli.battery, false)[0];
+                    match Thresholds::load(path, backends.for_battery(path).as_ref()) {
+                        Ok(t) => t,
+                        Err(e) => {
+                            eprintln!("Failed to load current thresholds: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ => {
+                    eprintln!("Error: --start and --end must be given together");
+                    std::process::exit(1);
+                }
+            };
+
+            if let Err(e) = thresholds.validate() {
+                eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
+
+            if let Err(e) = config::persist_profile(&layers.store, name, &thresholds) {
+                eprintln!("Failed to save config: {}", e);
+                std::process::exit(1);
+            }
+
+            println!(
+                "Saved profile {}: {}%-{}%",
+                name, thresholds.start, thresholds.end
+            );
+            warn_overridden(layers, &format!("profiles.{}", name));
+        }
+    }
+}
+
+fn start_topup(
+    targets: &[PathBuf],
+    backends: &Backends,
+    state_path: &Path,
+    timeout: Duration,
+    no_wait: bool,
+) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+    let mut remaining = Vec::new();
+
+    for path in targets {
+        let backend = backends.for_battery(path);
+        match topup::start(path, backend.as_ref(), state_path, timeout) {
+            Ok(report) => {
+                println!(
+                    "{}Topping up: thresholds raised to {}%-{}%",
+                    battery_prefix(path, single),
+                    report.accepted.start,
+                    report.accepted.end
+                );
+                remaining.push((path, backend));
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}Failed to start top-up: {}",
+                    battery_prefix(path, single),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if no_wait {
+        println!("Previous thresholds will be restored by `batty apply` once the battery is full.");
+    } else if !remaining.is_empty() {
+        println!("Waiting for the battery to be full. Previous thresholds are restored even if batty is stopped.");
+    }
+
+    while !no_wait && !remaining.is_empty() {
+        thread::sleep(TOPUP_POLL_INTERVAL);
+
+        remaining.retain(|(path, backend)| {
+            match topup::restore_if_complete(path, backend.as_ref(), state_path) {
+                Ok(Some(report)) => {
+                    println!(
+                        "{}Top-up finished, thresholds restored to {}%-{}%",
+                        battery_prefix(path, single),
+                        report.accepted.start,
+                        report.accepted.end
+                    );
+                    false
+                }
+                Ok(None) => true,
+                Err(e) => {
+                    eprintln!(
+                        "{}Failed to restore thresholds: {}",
+                        battery_prefix(path, single),
+                        e
+                    );
+                    failed = true;
+                    false
+                }
+            }
+        });
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn restore_topup(targets: &[PathBuf], backends: &Backends, state_path: &Path) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+
+    for path in targets {
+        let backend = backends.for_battery(path);
+        match topup::restore(path, backend.as_ref(), state_path) {
+            Ok(Some(report)) => println!(
+                "{}Thresholds restored to {}%-{}%",
+                battery_prefix(path, single),
+                report.accepted.start,
+                report.accepted.end
+            ),
+            Ok(None) => println!("{}No top-up pending", battery_prefix(path, single)),
+            Err(e) => {
+                eprintln!(
+                    "{}Failed to restore thresholds: {}",
+                    battery_prefix(path, single),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Starts or resumes calibrating each target and follows the steps until every
+/// calibration has finished. Progress is kept in the state file, so the wizard can be
+/// stopped and rerun at any time, including after a reboot.
+fn calibrate(targets: &[PathBuf], backends: &Backends, state_path: &Path) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+    let mut remaining = Vec::new();
+
+    for path in targets {
+        let prefix = battery_prefix(path, single);
+        let backend = backends.for_battery(path);
+
+        let phase = match calibrate::resume(path, backend.as_ref(), state_path) {
+            Ok(Some(calibrate::Progress::Waiting(phase) | calibrate::Progress::Entered(phase))) => {
+                println!(
+                    "{}Resuming calibration at step {}/3: {}",
+                    prefix,
+                    phase.step(),
+                    phase
+                );
+                phase
+            }
+            Ok(Some(calibrate::Progress::Finished(finished))) => {
+                report_calibration(&prefix, &finished);
+                continue;
+            }
+            Ok(None) => match calibrate::start(path, backend.as_ref(), state_path) {
+                Ok(report) => {
+                    println!(
+                        "{}Calibrating: thresholds raised to {}%-{}% until the end",
+                        prefix, report.accepted.start, report.accepted.end
+                    );
+                    println!(
+                        "{}Step 1/3: {}. {}",
+                        prefix,
+                        calibrate::Phase::Charge,
+                        calibrate::Phase::Charge.instructions(false)
+                    );
+                    calibrate::Phase::Charge
+                }
+                Err(e) => {
+                    eprintln!("{}Failed to start calibration: {}", prefix, e);
+                    failed = true;
+                    continue;
+                }
+            },
+            Err(e) => {
+                eprintln!("{}Failed to resume calibration: {}", prefix, e);
+                failed = true;
+                continue;
+            }
         };
 
-        if let Err(e) = thresholds.set(kind, value) {
+        if phase != calibrate::Phase::Charge {
+            println!(
+                "{}{}",
+                prefix,
+                phase.instructions(calibrate::can_force_discharge(path))
+            );
+        }
+        remaining.push((path, backend));
+    }
+
+    if !remaining.is_empty() {
+        println!("Progress is saved. Stop with Ctrl-C and rerun `batty calibrate` to continue, or cancel with `batty calibrate --abort`.");
+        println!("The calibration settings, including a forced discharge, stay in place after Ctrl-C until the calibration is continued or cancelled.");
+    }
+
+    loop {
+        remaining.retain(|(path, backend)| {
+            let prefix = battery_prefix(path, single);
+            match calibrate::advance(path, backend.as_ref(), state_path) {
+                Ok(Some(calibrate::Progress::Waiting(_))) => true,
+                Ok(Some(calibrate::Progress::Entered(phase))) => {
+                    println!(
+                        "{}Step {}/3: {}. {}",
+                        prefix,
+                        phase.step(),
+                        phase,
+                        phase.instructions(calibrate::can_force_discharge(path))
+                    );
+                    true
+                }
+                Ok(Some(calibrate::Progress::Finished(finished))) => {
+                    report_calibration(&prefix, &finished);
+                    false
+                }
+                Ok(None) => false,
+                Err(e) => {
+                    eprintln!("{}Failed to advance calibration: {}", prefix, e);
+                    failed = true;
+                    false
+                }
+            }
+        });
+
+        if remaining.is_empty() {
+            break;
+        }
+        thread::sleep(CALIBRATE_POLL_INTERVAL);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn report_calibration(prefix: &str, finished: &calibrate::Finished) {
+    println!(
+        "{}Calibration finished, thresholds restored to {}%-{}%",
+        prefix, finished.restored.accepted.start, finished.restored.accepted.end
+    );
+    println!("{}{}", prefix, finished.summary());
+}
+
+fn abort_calibration(targets: &[PathBuf], backends: &Backends, state_path: &Path) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+
+    for path in targets {
+        let backend = backends.for_battery(path);
+        match calibrate::abort(path, backend.as_ref(), state_path) {
+            Ok(Some(report)) => println!(
+                "{}Calibration cancelled, thresholds restored to {}%-{}%",
+                battery_prefix(path, single),
+                report.accepted.start,
+                report.accepted.end
+            ),
+            Ok(None) => println!("{}No calibration in progress", battery_prefix(path, single)),
+            Err(e) => {
+                eprintln!(
+                    "{}Failed to restore thresholds: {}",
+                    battery_prefix(path, single),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn show_history(batteries: &[String], since: &str, until: Option<&str>, dir: &Path, stats: bool) {
+    let now = state::now();
+    let range = history::parse_time(since, now).and_then(|since| {
+        let until = match until {
+            Some(until) => history::parse_time(until, now)?,
+            None => now + 1,
+        };
+        Ok((since, until))
+    });
+
+    let (since, until) = match range {
+        Ok(range) => range,
+        Err(e) => {
             eprintln!("Error: {}", e);
             std::process::exit(1);
         }
+    };
 
-        if let Err(e) = thresholds.save(battery_path) {
-            eprintln!("Failed to save thresholds: {}", e);
+    let samples = match History::new(dir.to_path_buf()).query(None, since, until) {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("Failed to read history from {}: {}", dir.display(), e);
             std::process::exit(1);
         }
+    };
 
-        println!("Battery charge {} threshold set to {}%", kind, value);
-    } else {
-        match Thresholds::load(battery_path) {
+    let mut names: Vec<&str> = samples.iter().map(|s| s.battery.as_str()).collect();
+    names.sort();
+    names.dedup();
+    names.retain(|name| batteries.is_empty() || batteries.iter().any(|b| b == name));
+
+    if names.is_empty() {
+        println!("No samples recorded in this range");
+        return;
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        let battery_samples: Vec<_> = samples
+            .iter()
+            .filter(|s| s.battery == *name)
+            .cloned()
+            .collect();
+
+        if stats {
+            if i > 0 {
+                println!();
+            }
+            if let Some(summary) = Summary::new(&battery_samples) {
+                print!("{}", summary.render(name));
+            }
+            continue;
+        }
+
+        for sample in battery_samples {
+            let health = sample
+                .health_percentage()
+                .map(|h| format!("{:.1}%", h))
+                .unwrap_or_else(|| "--".to_string());
+            let power = sample
+                .power
+                .map(|p| format!("{:.2} W", p as f32 / 1_000_000.0))
+                .unwrap_or_else(|| "--".to_string());
+            let thresholds = sample
+                .thresholds
+                .as_ref()
+                .map(|t| format!("{}-{}%", t.start, t.end))
+                .unwrap_or_else(|| "--".to_string());
+
+            println!(
+                "{}  {}  {:>6.1}%  {} health {:>6}  {:>8}  {}",
+                history::format_timestamp(sample.timestamp),
+                sample.battery,
+                sample.charge_percentage(),
+                report::paint_status(&format!("{:<13}", sample.status)),
+                health,
+                power,
+                thresholds
+            );
+        }
+    }
+}
+
+fn show_devices(power_supply_path: &Path, format: Option<OutputFormat>) {
+    let devices = device::find_devices(power_supply_path);
+
+    if let Some(format) = format {
+        match report::render_devices(&devices, format) {
+            Ok(output) => println!("{}", output.trim_end()),
+            Err(e) => {
+                eprintln!("Failed to render devices: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if devices.is_empty() {
+        println!("No peripheral batteries found");
+        return;
+    }
+
+    let name_width = devices
+        .iter()
+        .map(|d| d.display_name().chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("DEVICE".len());
+    let manufacturer_width = devices
+        .iter()
+        .filter_map(|d| d.manufacturer.as_ref().map(|m| m.chars().count()))
+        .max()
+        .unwrap_or(0)
+        .max("MANUFACTURER".len());
+
+    println!(
+        "{:<name_width$}  {:<manufacturer_width$}  {:<8}  STATUS",
+        "DEVICE", "MANUFACTURER", "LEVEL"
+    );
+    for device in &devices {
+        println!(
+            "{:<name_width$}  {:<manufacturer_width$}  {:<8}  {}",
+            device.display_name(),
+            device.manufacturer.as_deref().unwrap_or("--"),
+            device.level(),
+            report::paint_status(device.status.as_str())
+        );
+    }
+}
+
+fn set_charge_behaviour(targets: &[PathBuf], behaviour: ChargeBehaviour) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+
+    for path in targets {
+        match behaviour.save(path) {
+            Ok(()) => println!(
+                "{}Charge behaviour set to {}",
+                battery_prefix(path, single),
+                behaviour.as_str()
+            ),
+            Err(e) => {
+                eprintln!(
+                    "{}Failed to set charge behaviour: {}",
+                    battery_prefix(path, single),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn read_thresholds(targets: &[PathBuf], backends: &Backends) {
+    let mut failed = false;
+
+    for (i, path) in targets.iter().enumerate() {
+        if targets.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", battery_name(path));
+        }
+
+        let backend = backends.for_battery(path);
+        match Thresholds::load(path, backend.as_ref()) {
             Ok(thresholds) => {
                 println!("Current battery thresholds:");
                 println!("  Start: {}%", thresholds.start);
@@ -79,8 +1010,794 @@ fn main() {
             }
             Err(e) => {
                 eprintln!("Failed to read thresholds: {}", e);
+                failed = true;
+            }
+        }
+
+        let available = ChargeBehaviour::available(path);
+        if let Ok(behaviour) = ChargeBehaviour::load(path) {
+            let names: Vec<&str> = available.iter().map(ChargeBehaviour::as_str).collect();
+            println!(
+                "Charge behaviour: {} (available: {})",
+                behaviour.as_str(),
+                names.join(", ")
+            );
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the thresholds in a form scripts can parse: `START-END`, or the bare value of
+/// one kind, prefixed with the battery name when several are selected.
+fn get_thresholds(targets: &[PathBuf], backends: &Backends, kind: Option<ThresholdKind>) {
+    let single = targets.len() == 1;
+    let mut failed = false;
+
+    for path in targets {
+        let backend = backends.for_battery(path);
+        match Thresholds::load(path, backend.as_ref()) {
+            Ok(thresholds) => match kind {
+                Some(kind) => println!("{}{}", battery_prefix(path, single), thresholds.get(kind)),
+                None => println!(
+                    "{}{}-{}",
+                    battery_prefix(path, single),
+                    thresholds.start,
+                    thresholds.end
+                ),
+            },
+            Err(e) => {
+                eprintln!(
+                    "{}Failed to read thresholds: {}",
+                    battery_prefix(path, single),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn set_thresholds(
+    cli: &Cli,
+    targets: &[PathBuf],
+    backends: &Backends,
+    layers: &ConfigLayers,
+    state_path: &Path,
+    start: Option<u8>,
+    end: Option<u8>,
+) {
+    let changes: Vec<ThresholdKind> = [(ThresholdKind::Start, start), (ThresholdKind::End, end)]
+        .into_iter()
+        .filter_map(|(kind, value)| value.map(|_| kind))
+        .collect();
+
+    let single = targets.len() == 1;
+    update_thresholds(
+        targets,
+        backends,
+        cli.keep_going,
+        layers,
+        state_path,
+        |thresholds| {
+            // The pair is checked as a whole; the backend picks a write order the
+            // hardware accepts
+            thresholds
+                .with(start, end)
+                .map_err(|e| match changes.as_slice() {
+                    [_] => format!("{}; pass START-END to change both at once", e),
+                    _ => e,
+                })
+        },
+        |path, report| match changes.as_slice() {
+            [kind] => format!(
+                "{}Battery charge {} threshold set to {}%",
+                battery_prefix(path, single),
+                kind,
+                report.accepted.get(*kind)
+            ),
+            _ => format!(
+                "{}Battery charge thresholds set to {}%-{}%",
+                battery_prefix(path, single),
+                report.accepted.start,
+                report.accepted.end
+            ),
+        },
+    );
+}
+
+/// Either the human-readable thresholds of the selected batteries or, with a format, the
+/// full report of every selected battery.
+fn status(
+    cli: &Cli,
+    format: Option<OutputFormat>,
+    bat_paths: &[PathBuf],
+    power_supply_path: &Path,
+    backends: &Backends,
+) {
+    let Some(format) = format else {
+        // Without --battery or --all, only the first battery is shown
+        let targets = select_batteries(bat_paths, &cli.battery, cli.all);
+        read_thresholds(&targets, backends);
+        return;
+    };
+
+    let bat_paths = select_batteries(bat_paths, &cli.battery, true);
+    let devices = if cli.devices {
+        device::find_devices(power_supply_path)
+    } else {
+        Vec::new()
+    };
+
+    let report = Report::collect(
+        &bat_paths,
+        power_source::find_power_sources(power_supply_path),
+        devices,
+        backends,
+    );
+    match report.render(format) {
+        Ok(output) => println!("{}", output.trim_end()),
+        Err(e) => {
+            eprintln!("Failed to render report: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if report.has_errors() {
+        std::process::exit(1);
+    }
+}
+
+fn list_batteries(bat_paths: &[PathBuf], backends: &Backends) {
+    let name_width = bat_paths
+        .iter()
+        .map(|p| battery_name(p).len())
+        .max()
+        .unwrap_or(0)
+        .max("BATTERY".len());
+
+    println!(
+        "{:<name_width$}  {:<6}  {:<10}  {:<8}  STATUS",
+        "BATTERY", "CHARGE", "THRESHOLDS", "BACKEND"
+    );
+    for path in bat_paths {
+        let backend = backends.for_battery(path);
+        let thresholds = Thresholds::load(path, backend.as_ref())
+            .map(|t| format!("{}-{}%", t.start, t.end))
+            .unwrap_or_else(|_| "--".to_string());
+        let (charge, status) = match Battery::new(path) {
+            Ok((battery, _)) => (
+                format!("{:.0}%", battery.charge_percentage()),
+                report::paint_status(battery.status.as_str()),
+            ),
+            Err(_) => ("--".to_string(), "unknown".to_string()),
+        };
+
+        println!(
+            "{:<name_width$}  {:<6}  {:<10}  {:<8}  {}",
+            battery_name(path),
+            charge,
+            thresholds,
+            backend.name(),
+            status
+        );
+    }
+}
+
+/// Maps the flag-style invocation that predates the subcommands onto its subcommand,
+/// warning when a deprecated flag was used. Plain `batty` stays `batty status`.
+fn legacy_command(cli: &Cli) -> Command {
+    let changes = cli.value.is_some()
+        || cli.start.is_some()
+        || cli.end.is_some()
+        || cli.charge_behaviour.is_some();
+
+    let command = if cli.tui {
+        if changes {
+            eprintln!(
+                "Error: --value, --start, --end and --charge-behaviour cannot be used with --tui"
+            );
+            std::process::exit(1);
+        }
+        if cli.format.is_some() {
+            eprintln!("Error: --format cannot be used with --tui");
+            std::process::exit(1);
+        }
+        Command::Tui
+    } else if let Some(format) = cli.format {
+        if changes {
+            eprintln!(
+                "Error: --format cannot be used with --value, --start, --end or --charge-behaviour"
+            );
+            std::process::exit(1);
+        }
+        Command::Status {
+            format: Some(format),
+        }
+    } else if changes {
+        let kind = cli.kind.unwrap_or(ThresholdKind::End);
+        let value = |k| cli.value.filter(|_| kind == k);
+        Command::Set {
+            range: None,
+            start: cli.start.or_else(|| value(ThresholdKind::Start)),
+            end: cli.end.or_else(|| value(ThresholdKind::End)),
+            charge_behaviour: cli.charge_behaviour,
+        }
+    } else {
+        Command::Status { format: None }
+    };
+
+    if cli.tui || cli.format.is_some() || changes || cli.kind.is_some() {
+        eprintln!(
+            "Warning: flag-style options are deprecated; use `{}` instead",
+            command_line(&command)
+        );
+    }
+
+    command
+}
+
+/// The subcommand invocation equivalent to a legacy one, for the deprecation warning.
+fn command_line(command: &Command) -> String {
+    let mut line = String::from("batty");
+    match command {
+        Command::Tui => line.push_str(" tui"),
+        Command::Status { format } => {
+            line.push_str(" status");
+            if let Some(name) = format.and_then(|f| f.to_possible_value()) {
+                line.push_str(&format!(" --format {}", name.get_name()));
+            }
+        }
+        Command::Set {
+            start,
+            end,
+            charge_behaviour,
+            ..
+        } => {
+            line.push_str(" set");
+            if let Some(start) = start {
+                line.push_str(&format!(" --start {}", start));
+            }
+            if let Some(end) = end {
+                line.push_str(&format!(" --end {}", end));
+            }
+            if let Some(behaviour) = charge_behaviour {
+                line.push_str(&format!(" --charge-behaviour {}", behaviour.as_str()));
+            }
+        }
+        _ => {}
+    }
+    line
+}
+
+fn apply(
+    config: &Config,
+    config_path: &Path,
+    state_path: &Path,
+    power_supply_path: &Path,
+    backends: &Backends,
+) {
+    let state = match State::load(state_path) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to load state: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if config.batteries.is_empty() && state.topup.is_empty() && state.calibration.is_empty() {
+        println!("No thresholds stored in {}", config_path.display());
+        return;
+    }
+
+    let mut failed = false;
+    let mut in_progress = Vec::new();
+
+    // A top-up interrupted by a reboot or a killed process is finished here
+    for name in state.topup.keys() {
+        let battery_path = power_supply_path.join(name);
+        let backend = backends.for_battery(&battery_path);
+
+        match topup::restore_if_complete(&battery_path, backend.as_ref(), state_path) {
+            Ok(Some(report)) => println!(
+                "{}: top-up finished, thresholds restored to {}%-{}%",
+                name, report.accepted.start, report.accepted.end
+            ),
+            Ok(None) => {
+                println!("{}: top-up in progress, leaving thresholds raised", name);
+                in_progress.push(name);
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to restore thresholds after top-up for {}: {}",
+                    name, e
+                );
+                in_progress.push(name);
+                failed = true;
+            }
+        }
+    }
+
+    // Firmware may reset the calibration settings on reboot, so they are written again
+    for name in state.calibration.keys() {
+        let battery_path = power_supply_path.join(name);
+        let backend = backends.for_battery(&battery_path);
+
+        match calibrate::resume(&battery_path, backend.as_ref(), state_path) {
+            Ok(Some(
+                calibrate::Progress::Waiting(phase) | calibrate::Progress::Entered(phase),
+            )) => println!(
+                "{}: calibration in progress ({}), run `batty calibrate` to continue or `batty calibrate --abort` to cancel",
+                name, phase
+            ),
+            Ok(Some(calibrate::Progress::Finished(finished))) => {
+                report_calibration(&format!("{}: ", name), &finished);
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Failed to resume calibration of {}: {}", name, e);
+                failed = true;
+            }
+        }
+        in_progress.push(name);
+    }
+
+    for (name, thresholds) in &config.batteries {
+        if in_progress.contains(&name) {
+            continue;
+        }
+
+        let battery_path = power_supply_path.join(name);
+
+        if let Err(e) = thresholds.validate() {
+            eprintln!("Error: invalid thresholds for {}: {}", name, e);
+            failed = true;
+            continue;
+        }
+
+        if !battery_path.exists() {
+            eprintln!(
+                "Error: battery {} not found in {}",
+                name,
+                power_supply_path.display()
+            );
+            failed = true;
+            continue;
+        }
+
+        match thresholds.save(&battery_path, backends.for_battery(&battery_path).as_ref()) {
+            Ok(report) => {
+                println!(
+                    "{}: thresholds set to {}%-{}%",
+                    name, report.accepted.start, report.accepted.end
+                );
+                for message in report.clamp_messages() {
+                    eprintln!("{}: Warning: firmware {}", name, message);
+                }
+                record_applied(state_path, &battery_path, &report.accepted);
+            }
+            Err(e) => {
+                eprintln!("Failed to apply thresholds to {}: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Reports batteries whose thresholds differ from the ones batty last applied and exits
+/// non-zero if any do or can't be read, so scripts and timers can react.
+fn check(targets: &[PathBuf], backends: &Backends, state_path: &Path) {
+    let state = match State::load(state_path) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to load state: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+
+    for path in targets {
+        let name = battery_name(path);
+        let backend = backends.for_battery(path);
+
+        match drift::detect(path, backend.as_ref(), &state) {
+            Ok(Some(drift)) => {
+                println!("{}: {}", name, drift);
+                failed = true;
+            }
+            Ok(None) => match state.applied.get(name) {
+                Some(t) => println!("{}: ok ({}%-{}%)", name, t.start, t.end),
+                None => println!("{}: no thresholds applied by batty yet", name),
+            },
+            Err(e) => {
+                eprintln!("Failed to read thresholds for {}: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Config overrides given on the command line: `--path`, `--backend` and every `--set`.
+fn cli_overrides(cli: &Cli) -> Vec<Override> {
+    let mut overrides = Vec::new();
+
+    if let Some(path) = &cli.path {
+        overrides.push(Override {
+            source: Source::Cli("--path".to_string()),
+            key: "path".to_string(),
+            value: toml::Value::String(path.display().to_string()),
+        });
+    }
+    if let Some(value) = cli.backend.and_then(|b| b.to_possible_value()) {
+        overrides.push(Override {
+            source: Source::Cli("--backend".to_string()),
+            key: "backend".to_string(),
+            value: toml::Value::String(value.get_name().to_string()),
+        });
+    }
+    for assignment in &cli.set {
+        match Override::parse_assignment(assignment) {
+            Ok(o) => overrides.push(o),
+            Err(e) => {
+                eprintln!("Error: invalid --set: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    overrides
+}
+
+fn show_config(layers: &ConfigLayers) {
+    let resolved = match layers.resolve() {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            eprintln!("Run `batty config validate` for details.");
+            std::process::exit(1);
+        }
+    };
+
+    println!("# Layers, lowest precedence first:");
+    println!("#   default");
+    for path in layers.files() {
+        let missing = if path.exists() { "" } else { " (not found)" };
+        println!("#   {}{}", path.display(), missing);
+    }
+    for o in &layers.overrides {
+        println!("#   {}", o.source);
+    }
+    println!();
+
+    let entries: Vec<(String, &Source)> = resolved
+        .entries()
+        .into_iter()
+        .map(|(key, value, source)| (format!("{} = {}", key, value), source))
+        .collect();
+    // Long arrays would push every comment far to the right, so they don't count
+    let width = entries
+        .iter()
+        .map(|(line, _)| line.len())
+        .filter(|len| *len <= SHOW_CONFIG_WIDTH)
+        .max()
+        .unwrap_or(0);
+
+    for (line, source) in entries {
+        println!("{:<width$}  # {}", line, source, width = width);
+    }
+}
+
+/// Checks each config file on its own, then the merged config with its overrides, and
+/// exits non-zero if anything is wrong.
+fn validate_config(layers: &ConfigLayers) {
+    let mut failed = false;
+
+    for path in layers.files() {
+        if !path.exists() {
+            println!("{}: not found, skipped", path.display());
+            continue;
+        }
+        match Config::load(path) {
+            Ok(_) => println!("{}: ok", path.display()),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    let resolved = match layers.resolve() {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    for problem in resolved.config.validate() {
+        let mut sources: Vec<String> = Vec::new();
+        for key in &problem.keys {
+            for source in resolved.sources_of(key) {
+                if !sources.contains(&source.to_string()) {
+                    sources.push(source.to_string());
+                }
+            }
+        }
+        eprintln!(
+            "{}: {} (set in {})",
+            problem.keys.join(", "),
+            problem.message,
+            sources.join(", ")
+        );
+        failed = true;
+    }
+
+    for path in layers.files() {
+        let overridden = match layers.overridden(path) {
+            Ok(overridden) => overridden,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        for (key, sources) in overridden {
+            let sources: Vec<String> = sources.iter().map(|source| source.to_string()).collect();
+            eprintln!(
+                "Warning: {} in {} is overridden by {}",
+                key,
+                path.display(),
+                sources.join(", ")
+            );
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+    println!("Config is valid");
+}
+
+/// Opens the user config, or the system one, in the user's editor and validates it once
+/// the editor exits.
+fn edit_config(layers: &ConfigLayers, system: bool) {
+    let path = if system {
+        layers.system.clone()
+    } else {
+        match &layers.user {
+            Some(path) => path.clone(),
+            None => {
+                eprintln!("Error: no user config directory; set XDG_CONFIG_HOME or HOME");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    if let Some(parent) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            eprintln!("Failed to create {}: {}", parent.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Run through the shell so editors configured with arguments, like `code --wait`, work
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => validate_config(layers),
+        Ok(status) => {
+            eprintln!("Error: {} exited with {}", editor, status);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to run {}: {}", editor, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn install_service(cli: &Cli, dry_run: bool, daemon: bool) {
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/bin/batty"));
+
+    let mut apply_args = Vec::new();
+    if let Some(path) = &cli.path {
+        apply_args.push(format!("--path {}", path.display()));
+    }
+    if let Some(config) = &cli.config {
+        apply_args.push(format!("--config {}", config.display()));
+    }
+    if let Some(state) = &cli.state {
+        apply_args.push(format!("--state {}", state.display()));
+    }
+    if let Some(backend) = cli.backend {
+        if let Some(value) = backend.to_possible_value() {
+            apply_args.push(format!("--backend {}", value.get_name()));
+        }
+    }
+
+    let files = ServiceFiles::generate(&exe, &apply_args);
+    let daemon_files = daemon.then(|| DaemonFiles::generate(&exe, &apply_args));
+
+    if dry_run {
+        println!("# {}", service::UNIT_PATH);
+        println!("{}", files.unit);
+        println!("# {}", service::SLEEP_HOOK_PATH);
+        print!("{}", files.sleep_hook);
+        if let Some(daemon_files) = &daemon_files {
+            println!("\n# {}", service::DAEMON_UNIT_PATH);
+            println!("{}", daemon_files.unit);
+            println!("# {}", service::DBUS_POLICY_PATH);
+            println!("{}", daemon_files.dbus_policy);
+            println!("# {}", service::POLKIT_POLICY_PATH);
+            print!("{}", daemon_files.polkit_policy);
+        }
+        return;
+    }
+
+    let result = files.install().and_then(|mut paths| {
+        if let Some(daemon_files) = &daemon_files {
+            paths.extend(daemon_files.install()?);
+        }
+        Ok(paths)
+    });
+
+    match result {
+        Ok(paths) => {
+            for path in paths {
+                println!("Wrote {}", path.display());
+            }
+            println!("Enable it with: systemctl enable batty.service");
+            if daemon {
+                println!(
+                    "Start the D-Bus service with: systemctl enable --now batty-daemon.service"
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to install service: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Grants `group` write access to the threshold files of every battery, or removes the
+/// files doing so with `uninstall`.
+fn setup_permissions(
+    power_supply_path: &Path,
+    backends: &Backends,
+    group: &str,
+    dry_run: bool,
+    uninstall: bool,
+) {
+    if uninstall {
+        if dry_run {
+            println!("Would remove {}", service::UDEV_RULES_PATH);
+            println!("Would remove {}", service::TMPFILES_PATH);
+            return;
+        }
+
+        match PermissionFiles::uninstall() {
+            Ok(removed) if removed.is_empty() => println!("No permission files installed"),
+            Ok(removed) => {
+                for path in removed {
+                    println!("Removed {}", path.display());
+                }
+                println!("Permissions already granted stay in place until the next reboot.");
+                println!(
+                    "{} and {} keep their group write access; remove it with chmod g-w.",
+                    state::DEFAULT_STATE_PATH,
+                    config::SHARED_STORE_PATH
+                );
+            }
+            Err(e) => {
+                eprintln!("{}", e);
                 std::process::exit(1);
             }
         }
+        return;
+    }
+
+    let mut devices = Vec::new();
+    for path in discover_batteries(power_supply_path) {
+        let files = backends.for_battery(&path).files(&path);
+        if files.is_empty() {
+            eprintln!(
+                "Warning: no threshold files found for {}",
+                battery_name(&path)
+            );
+        }
+        for file in files.into_iter().chain(ChargeBehaviour::file(&path)) {
+            WritableDevice::add(&mut devices, &path, file);
+        }
+    }
+
+    if devices.is_empty() {
+        eprintln!("Error: no threshold files found");
+        std::process::exit(1);
+    }
+
+    let files = PermissionFiles::generate(&devices, group);
+
+    if dry_run {
+        println!("# {}", service::UDEV_RULES_PATH);
+        println!("{}", files.rules);
+        println!("# {}", service::TMPFILES_PATH);
+        print!("{}", files.tmpfiles);
+        return;
+    }
+
+    if !group_exists(group) {
+        eprintln!(
+            "Warning: group {} does not exist; create it with: groupadd {}",
+            group, group
+        );
+    }
+
+    match files.install() {
+        Ok(paths) => {
+            for path in paths {
+                println!("Wrote {}", path.display());
+            }
+            println!(
+                "Apply now with: systemd-tmpfiles --create {}",
+                service::TMPFILES_PATH
+            );
+            println!("Add users to the group with: usermod -aG {} <user>", group);
+            println!(
+                "Thresholds are now saved to {}, which `batty apply` restores at boot.",
+                config::SHARED_STORE_PATH
+            );
+        }
+        Err(e) => {
+            eprintln!("Failed to set up permissions: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn group_exists(group: &str) -> bool {
+    std::fs::read_to_string("/etc/group")
+        .map(|groups| {
+            groups
+                .lines()
+                .any(|line| line.split(':').next() == Some(group))
+        })
+        .unwrap_or(true)
+}
+
+/// Prefixes per-battery messages with the battery name when more than one is targeted.
+fn battery_prefix(path: &Path, single: bool) -> String {
+    if single {
+        String::new()
+    } else {
+        format!("{}: ", battery_name(path))
     }
 }
@@ -0,0 +1,253 @@
+use crate::{
+    battery::{battery_name, Battery, BatteryScope, BatteryStatus, ChargeBehaviour},
+    thresholds::Thresholds,
+};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Barrel or proprietary AC adapter (`type` is `Mains`).
+    Mains,
+    /// USB, usually a USB-C port managed by UCSI (`type` is `USB`).
+    Usb,
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mains => "mains",
+            Self::Usb => "usb",
+        }
+    }
+}
+
+/// A charger input such as `AC`, `ADP1` or `ucsi-source-psy-*`. Electrical values are
+/// converted from sysfs µV and µA and are `None` when the driver doesn't report them.
+#[derive(Clone, Serialize)]
+pub struct PowerSource {
+    pub name: String,
+    pub kind: SourceKind,
+    pub online: bool,
+    /// Active USB charging protocol, e.g. `PD` or `PD_PPS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_type: Option<String>,
+    /// Negotiated voltage in volts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f32>,
+    /// Negotiated current limit in amperes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<f32>,
+    /// Most the source can deliver, in watts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_power: Option<f32>,
+}
+
+impl PowerSource {
+    /// Reads the power supply at `path`, or `None` if it isn't a mains or USB source.
+    pub fn new(path: &Path) -> Option<Self> {
+        let kind = match read_attribute(path, "type")?.as_str() {
+            "Mains" => SourceKind::Mains,
+            t if t.starts_with("USB") => SourceKind::Usb,
+            _ => return None,
+        };
+
+        let micro = |name| read_attribute(path, name)?.parse::<u32>().ok();
+        let voltage = micro("voltage_now").map(|uv| uv as f32 / 1_000_000.0);
+        let current = micro("current_max").map(|ua| ua as f32 / 1_000_000.0);
+        let max_voltage = micro("voltage_max").map(|uv| uv as f32 / 1_000_000.0);
+
+        Some(Self {
+            name: battery_name(path).to_string(),
+            kind,
+            online: read_attribute(path, "online").is_some_and(|o| o != "0"),
+            usb_type: read_attribute(path, "usb_type").and_then(|t| active_usb_type(&t)),
+            voltage,
+            current,
+            max_power: max_voltage.or(voltage).zip(current).map(|(v, a)| v * a),
+        })
+    }
+
+    /// Short description of the negotiated contract, e.g. `PD 20.0 V 3.00 A (60 W)`.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.usb_type.clone().unwrap_or_else(|| match self.kind {
+            SourceKind::Mains => "AC".to_string(),
+            SourceKind::Usb => "USB".to_string(),
+        })];
+
+        if let Some(voltage) = self.voltage {
+            parts.push(format!("{:.1} V", voltage));
+        }
+        if let Some(current) = self.current {
+            parts.push(format!("{:.2} A", current));
+        }
+        if let Some(max_power) = self.max_power {
+            parts.push(format!("({:.0} W)", max_power));
+        }
+
+        parts.join(" ")
+    }
+}
+
+/// Picks the bracketed entry of a `usb_type` list like `C [PD] PD_PPS`.
+fn active_usb_type(usb_type: &str) -> Option<String> {
+    usb_type
+        .split_whitespace()
+        .find_map(|word| word.strip_prefix('[')?.strip_suffix(']'))
+        .map(str::to_string)
+}
+
+/// Finds every mains and USB power supply, sorted by name.
+pub fn find_power_sources(power_supply_path: &Path) -> Vec<PowerSource> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(power_supply_path)
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+
+    paths.iter().filter_map(|p| PowerSource::new(p)).collect()
+}
+
+/// Explains why a discharging or not charging battery isn't charging: no charger,
+/// a threshold hold, `charge_behaviour`, or a charger too weak for the load, and
+/// "reason unknown" when none of them is borne out by sysfs. `None` when the battery is
+/// charging or is a peripheral.
+pub fn not_charging_reason(
+    battery: &Battery,
+    sources: &[PowerSource],
+    thresholds: Option<&Thresholds>,
+) -> Option<String> {
+    if !matches!(
+        battery.status,
+        BatteryStatus::Discharging | BatteryStatus::NotCharging
+    ) || battery.scope != BatteryScope::System
+        || sources.is_empty()
+    {
+        return None;
+    }
+
+    let online: Vec<&PowerSource> = sources.iter().filter(|s| s.online).collect();
+    if online.is_empty() {
+        return Some("no charger connected".to_string());
+    }
+
+    match battery.charge_behaviour {
+        Some(ChargeBehaviour::InhibitCharge | ChargeBehaviour::InhibitChargeAwake) => {
+            return Some("inhibited by charge_behaviour".to_string());
+        }
+        Some(ChargeBehaviour::ForceDischarge) => {
+            return Some("discharge forced by charge_behaviour".to_string());
+        }
+        _ => {}
+    }
+
+    let charge = battery.charge_percentage();
+    if let Some(t) = thresholds {
+        let held = charge >= t.end as f32
+            || (battery.status == BatteryStatus::NotCharging && charge >= t.start as f32);
+        if held {
+            return Some(format!("held by thresholds ({}-{}%)", t.start, t.end));
+        }
+    }
+
+    // The battery supplying power while a charger is online means the charger can't
+    // cover the load, but only claim that when both sides were measured
+    let max_power = online.iter().filter_map(|s| s.max_power).reduce(f32::max);
+    let supplied = battery
+        .readings
+        .power
+        .filter(|watts| battery.status == BatteryStatus::Discharging && *watts > 0.0);
+    Some(match (max_power, supplied) {
+        (Some(max), Some(watts)) => format!(
+            "charger too weak ({:.0} W max, battery supplying {:.1} W)",
+            max, watts
+        ),
+        _ => "reason unknown".to_string(),
+    })
+}
+
+fn read_attribute(path: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(path.join(name))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battery(status: &str, power_now: Option<&str>) -> (tempfile::TempDir, Battery) {
+        let dir = tempfile::tempdir().unwrap();
+        for (attr, value) in [
+            ("type", "Battery"),
+            ("scope", "System"),
+            ("status", status),
+            ("energy_now", "30000000"),
+            ("energy_full", "50000000"),
+        ] {
+            fs::write(dir.path().join(attr), value).unwrap();
+        }
+        if let Some(power) = power_now {
+            fs::write(dir.path().join("power_now"), power).unwrap();
+        }
+
+        let (battery, _) = Battery::new(dir.path()).unwrap();
+        (dir, battery)
+    }
+
+    fn charger(max_power: Option<f32>) -> PowerSource {
+        PowerSource {
+            name: "ucsi-source-psy-USBC000:001".to_string(),
+            kind: SourceKind::Usb,
+            online: true,
+            usb_type: None,
+            voltage: None,
+            current: None,
+            max_power,
+        }
+    }
+
+    #[test]
+    fn blames_the_charger_only_when_measured() {
+        let (_dir, discharging) = battery("Discharging", Some("8000000"));
+        assert_eq!(
+            not_charging_reason(&discharging, &[charger(Some(15.0))], None).as_deref(),
+            Some("charger too weak (15 W max, battery supplying 8.0 W)")
+        );
+        assert_eq!(
+            not_charging_reason(&discharging, &[charger(None)], None).as_deref(),
+            Some("reason unknown")
+        );
+
+        let (_dir, unmeasured) = battery("Discharging", None);
+        assert_eq!(
+            not_charging_reason(&unmeasured, &[charger(Some(15.0))], None).as_deref(),
+            Some("reason unknown")
+        );
+
+        let (_dir, not_charging) = battery("Not charging", Some("0"));
+        assert_eq!(
+            not_charging_reason(&not_charging, &[charger(Some(15.0))], None).as_deref(),
+            Some("reason unknown")
+        );
+    }
+
+    #[test]
+    fn reports_threshold_holds_before_the_charger() {
+        let (_dir, battery) = battery("Not charging", None);
+        let thresholds = Thresholds { start: 40, end: 80 };
+
+        assert_eq!(
+            not_charging_reason(&battery, &[charger(Some(15.0))], Some(&thresholds)).as_deref(),
+            Some("held by thresholds (40-80%)")
+        );
+        assert_eq!(not_charging_reason(&battery, &[], Some(&thresholds)), None);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{config::Config, thresholds::Thresholds};
+
+pub const DEFAULT_PROFILE: &str = "desk";
+pub const DEFAULT_THRESHOLDS: Thresholds = Thresholds { start: 40, end: 80 };
+
+/// Presets shipped with batty. Profiles in the config with the same name take precedence.
+const BUILTIN_PROFILES: [(&str, Thresholds); 3] = [
+    (DEFAULT_PROFILE, DEFAULT_THRESHOLDS),
+    (
+        "travel",
+        Thresholds {
+            start: 95,
+            end: 100,
+        },
+    ),
+    ("storage", Thresholds { start: 45, end: 50 }),
+];
+
+#[derive(Clone)]
+pub struct Profile {
+    pub name: String,
+    pub thresholds: Thresholds,
+    pub builtin: bool,
+}
+
+impl Profile {
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN_PROFILES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(name, thresholds)| Self {
+                name: name.to_string(),
+                thresholds: thresholds.clone(),
+                builtin: true,
+            })
+    }
+
+    /// Built-in presets in shipping order, followed by the user's own sorted by name.
+    pub fn all(config: &Config) -> Vec<Self> {
+        let mut profiles: Vec<Self> = BUILTIN_PROFILES
+            .iter()
+            .filter(|(name, _)| !config.profiles.contains_key(*name))
+            .filter_map(|(name, _)| Self::builtin(name))
+            .collect();
+
+        profiles.extend(config.profiles.iter().map(|(name, thresholds)| Self {
+            name: name.clone(),
+            thresholds: thresholds.clone(),
+            builtin: false,
+        }));
+
+        profiles
+    }
+
+    pub fn find(config: &Config, name: &str) -> Option<Self> {
+        Self::all(config).into_iter().find(|p| p.name == name)
+    }
+}
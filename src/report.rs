@@ -0,0 +1,359 @@
+use crate::{
+    backend::{Backends, Capability},
+    battery::{
+        battery_name, format_duration, Battery, BatteryStatus, CapacityUnit, ChargeBehaviour,
+        ChargeType,
+    },
+    device::Device,
+    power_source::{not_charging_reason, PowerSource},
+    thresholds::Thresholds,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::Path,
+    time::Duration,
+};
+
+/// Bumped whenever a field is renamed or removed from the report.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Plain,
+    Json,
+    Toml,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub schema_version: u32,
+    pub batteries: Vec<BatteryReport>,
+    pub power_sources: Vec<PowerSource>,
+    /// Peripheral batteries, only collected with `--devices`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<Device>,
+}
+
+#[derive(Serialize)]
+struct DeviceList<'a> {
+    schema_version: u32,
+    devices: &'a [Device],
+}
+
+#[derive(Serialize)]
+pub struct BatteryReport {
+    pub name: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<BatteryInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<ThresholdsInfo>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatteryInfo {
+    pub charge_percent: f32,
+    /// charging, discharging, not charging, full or unknown.
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charge_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charge_behaviour: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cycles: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_percent: Option<f32>,
+    /// `energy` (µWh), `charge` (µAh) or `percent`, the unit of the capacity fields below.
+    pub unit: CapacityUnit,
+    pub now: u32,
+    pub full: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_design: Option<u32>,
+    /// Seconds until a charging battery reaches its end threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_threshold_secs: Option<u64>,
+    /// Seconds until a discharging battery is empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_empty_secs: Option<u64>,
+    /// Why a discharging or not charging battery isn't charging, when it can be told.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_charging_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ThresholdsInfo {
+    pub start: u8,
+    pub end: u8,
+    pub backend: String,
+    pub capability: Capability,
+}
+
+impl BatteryReport {
+    /// Reads everything batty knows about one battery. Failures are recorded in `errors`
+    /// instead of aborting, so one broken battery doesn't hide the others.
+    pub fn collect(path: &Path, sources: &[PowerSource], backends: &Backends) -> Self {
+        let name = battery_name(path).to_string();
+
+        let mut warnings = Vec::new();
+        let mut errors = Vec::new();
+
+        let backend = backends.for_battery(path);
+        let loaded = Thresholds::load(path, backend.as_ref())
+            .map_err(|e| errors.push(format!("Failed to read thresholds for {}: {}", name, e)))
+            .ok();
+        let thresholds = loaded.as_ref().map(|t| ThresholdsInfo {
+            start: t.start,
+            end: t.end,
+            backend: backend.name().to_string(),
+            capability: backend.capability(path),
+        });
+
+        let battery = match Battery::new(path) {
+            Ok((battery, battery_warnings)) => {
+                warnings.extend(battery_warnings);
+                Some(BatteryInfo::new(&battery, loaded.as_ref(), sources))
+            }
+            Err(e) => {
+                errors.push(e.to_string());
+                None
+            }
+        };
+
+        Self {
+            name,
+            path: path.display().to_string(),
+            battery,
+            thresholds,
+            warnings,
+            errors,
+        }
+    }
+}
+
+impl BatteryInfo {
+    /// Snapshot of `battery`. Its thresholds set the target of the time-to-charge estimate
+    /// and, with the power sources, explain why it isn't charging.
+    fn new(battery: &Battery, thresholds: Option<&Thresholds>, sources: &[PowerSource]) -> Self {
+        let end = thresholds.map_or(100, |t| t.end);
+
+        Self {
+            charge_percent: battery.charge_percentage(),
+            status: battery.status.as_str().to_string(),
+            charge_type: battery.charge_type.map(|t| t.as_str().to_string()),
+            charge_behaviour: battery.charge_behaviour.map(|b| b.as_str().to_string()),
+            cycles: battery.cycles,
+            health_percent: battery.health_percentage(),
+            unit: battery.unit,
+            now: battery.curr_power,
+            full: battery.total_power,
+            full_design: battery.design_power,
+            time_to_threshold_secs: battery.time_to_threshold(end).map(|d| d.as_secs()),
+            time_to_empty_secs: battery.time_to_empty().map(|d| d.as_secs()),
+            not_charging_reason: not_charging_reason(battery, sources, thresholds),
+        }
+    }
+}
+
+impl Report {
+    pub fn collect(
+        bat_paths: &[impl AsRef<Path>],
+        power_sources: Vec<PowerSource>,
+        devices: Vec<Device>,
+        backends: &Backends,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            batteries: bat_paths
+                .iter()
+                .map(|p| BatteryReport::collect(p.as_ref(), &power_sources, backends))
+                .collect(),
+            power_sources,
+            devices,
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.batteries.iter().any(|b| !b.errors.is_empty())
+    }
+
+    pub fn render(&self, format: OutputFormat) -> Result<String, String> {
+        match format {
+            OutputFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            OutputFormat::Toml => toml::to_string(self).map_err(|e| e.to_string()),
+            OutputFormat::Plain => Ok(self.render_plain()),
+        }
+    }
+
+    fn render_plain(&self) -> String {
+        let mut out = String::new();
+
+        for (i, report) in self.batteries.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}:\n", report.name));
+
+            if let Some(battery) = &report.battery {
+                out.push_str(&format!("  Charge: {:.2}%\n", battery.charge_percent));
+                out.push_str(&format!("  Status: {}\n", paint_status(&battery.status)));
+                if let Some(reason) = &battery.not_charging_reason {
+                    out.push_str(&format!("  Not charging: {}\n", reason));
+                }
+                if let Some(charge_type) = &battery.charge_type {
+                    out.push_str(&format!(
+                        "  Charge type: {}\n",
+                        paint(
+                            ChargeType::parse(charge_type).map_or("", charge_type_color),
+                            charge_type
+                        )
+                    ));
+                }
+                if let Some(behaviour) = &battery.charge_behaviour {
+                    out.push_str(&format!(
+                        "  Charge behaviour: {}\n",
+                        paint(
+                            ChargeBehaviour::parse(behaviour).map_or("", charge_behaviour_color),
+                            behaviour
+                        )
+                    ));
+                }
+                if let Some(secs) = battery.time_to_threshold_secs {
+                    let end = report.thresholds.as_ref().map_or(100, |t| t.end);
+                    out.push_str(&format!(
+                        "  Time to {}%: {}\n",
+                        end,
+                        format_duration(Duration::from_secs(secs))
+                    ));
+                }
+                if let Some(secs) = battery.time_to_empty_secs {
+                    out.push_str(&format!(
+                        "  Time to empty: {}\n",
+                        format_duration(Duration::from_secs(secs))
+                    ));
+                }
+                if let Some(cycles) = battery.cycles {
+                    out.push_str(&format!("  Cycles: {}\n", cycles));
+                }
+                if let Some(health) = battery.health_percent {
+                    out.push_str(&format!("  Health: {:.1}%\n", health));
+                }
+            }
+
+            if let Some(thresholds) = &report.thresholds {
+                out.push_str(&format!("  Start:  {}%\n", thresholds.start));
+                out.push_str(&format!("  End:    {}%\n", thresholds.end));
+                out.push_str(&format!(
+                    "  Backend: {} ({})\n",
+                    thresholds.backend, thresholds.capability
+                ));
+            }
+
+            for warning in &report.warnings {
+                out.push_str(&format!("  Warning: {}\n", warning));
+            }
+            for error in &report.errors {
+                out.push_str(&format!("  Error: {}\n", error));
+            }
+        }
+
+        for source in &self.power_sources {
+            out.push_str(&format!("\n{}:\n", source.name));
+            out.push_str(&format!("  Type:   {}\n", source.kind.as_str()));
+            out.push_str(&format!(
+                "  Online: {}\n",
+                if source.online { "yes" } else { "no" }
+            ));
+            if source.online {
+                out.push_str(&format!("  Supply: {}\n", source.describe()));
+            }
+        }
+
+        render_devices_plain(&mut out, &self.devices);
+
+        out
+    }
+}
+
+/// Renders the output of `batty devices --format`.
+pub fn render_devices(devices: &[Device], format: OutputFormat) -> Result<String, String> {
+    let list = DeviceList {
+        schema_version: SCHEMA_VERSION,
+        devices,
+    };
+
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&list).map_err(|e| e.to_string()),
+        OutputFormat::Toml => toml::to_string(&list).map_err(|e| e.to_string()),
+        OutputFormat::Plain => {
+            let mut out = String::new();
+            render_devices_plain(&mut out, devices);
+            Ok(out.trim_start().to_string())
+        }
+    }
+}
+
+fn render_devices_plain(out: &mut String, devices: &[Device]) {
+    for device in devices {
+        out.push_str(&format!("\n{} ({}):\n", device.display_name(), device.name));
+        if let Some(manufacturer) = &device.manufacturer {
+            out.push_str(&format!("  Manufacturer: {}\n", manufacturer));
+        }
+        out.push_str(&format!("  Level:  {}\n", device.level()));
+        out.push_str(&format!(
+            "  Status: {}\n",
+            paint_status(device.status.as_str())
+        ));
+    }
+}
+
+/// Whether plain output is coloured: stdout must be a terminal and `NO_COLOR` unset.
+fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+/// Wraps `text` in an ANSI SGR `code` when colour is enabled. An empty code leaves it plain.
+fn paint(code: &str, text: &str) -> String {
+    if code.is_empty() || !use_color() {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// Colours `text` by the status it names; padding should be applied beforehand so
+/// escape codes don't upset alignment.
+pub fn paint_status(text: &str) -> String {
+    paint(BatteryStatus::parse(text).map_or("", status_color), text)
+}
+
+fn status_color(status: BatteryStatus) -> &'static str {
+    match status {
+        BatteryStatus::Charging => "32",
+        BatteryStatus::Discharging => "33",
+        BatteryStatus::NotCharging => "36",
+        BatteryStatus::Full => "34",
+        BatteryStatus::Unknown => "90",
+    }
+}
+
+fn charge_type_color(charge_type: ChargeType) -> &'static str {
+    match charge_type {
+        ChargeType::Fast => "35",
+        ChargeType::Standard => "",
+        ChargeType::Trickle => "36",
+        ChargeType::Adaptive | ChargeType::Custom | ChargeType::LongLife => "34",
+        ChargeType::Bypass => "33",
+    }
+}
+
+fn charge_behaviour_color(behaviour: ChargeBehaviour) -> &'static str {
+    match behaviour {
+        ChargeBehaviour::Auto => "",
+        ChargeBehaviour::InhibitCharge | ChargeBehaviour::InhibitChargeAwake => "33",
+        ChargeBehaviour::ForceDischarge => "31",
+    }
+}
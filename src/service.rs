@@ -0,0 +1,321 @@
+use crate::{config::SHARED_STORE_PATH, daemon, state::DEFAULT_STATE_PATH};
+use std::{
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+pub const UNIT_PATH: &str = "/etc/systemd/system/batty.service";
+pub const SLEEP_HOOK_PATH: &str = "/usr/lib/systemd/system-sleep/batty";
+pub const DAEMON_UNIT_PATH: &str = "/etc/systemd/system/batty-daemon.service";
+pub const DBUS_POLICY_PATH: &str = "/usr/share/dbus-1/system.d/org.batty.Manager.conf";
+pub const POLKIT_POLICY_PATH: &str = "/usr/share/polkit-1/actions/org.batty.policy";
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/70-batty.rules";
+pub const TMPFILES_PATH: &str = "/etc/tmpfiles.d/batty.conf";
+
+pub struct ServiceFiles {
+    pub unit: String,
+    pub sleep_hook: String,
+}
+
+impl ServiceFiles {
+    /// Builds the boot unit and resume hook that run `batty apply` with the given arguments.
+    pub fn generate(exe: &Path, apply_args: &[String]) -> Self {
+        let mut command = format!("{} apply", exe.display());
+        for arg in apply_args {
+            command.push(' ');
+            command.push_str(arg);
+        }
+
+        let unit = format!(
+            "[Unit]\n\
+             Description=Apply batty battery charge thresholds\n\
+             After=sysinit.target\n\
+             \n\
+             [Service]\n\
+             Type=oneshot\n\
+             ExecStart={}\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            command
+        );
+
+        let sleep_hook = format!(
+            "#!/bin/sh\n\
+             # Firmware often resets charge thresholds on resume; reapply them.\n\
+             case \"$1\" in\n    \
+                 post) {} ;;\n\
+             esac\n",
+            command
+        );
+
+        Self { unit, sleep_hook }
+    }
+
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let unit_path = PathBuf::from(UNIT_PATH);
+        let hook_path = PathBuf::from(SLEEP_HOOK_PATH);
+
+        write_file(&unit_path, &self.unit, 0o644)?;
+        write_file(&hook_path, &self.sleep_hook, 0o755)?;
+
+        Ok(vec![unit_path, hook_path])
+    }
+}
+
+/// Files that let `batty daemon` own `org.batty.Manager` on the system bus and let
+/// active local users change thresholds after authenticating as an administrator.
+pub struct DaemonFiles {
+    pub unit: String,
+    pub dbus_policy: String,
+    pub polkit_policy: String,
+}
+
+impl DaemonFiles {
+    pub fn generate(exe: &Path, daemon_args: &[String]) -> Self {
+        let mut command = format!("{}", exe.display());
+        for arg in daemon_args {
+            command.push(' ');
+            command.push_str(arg);
+        }
+        command.push_str(" daemon");
+
+        let unit = format!(
+            "[Unit]\n\
+             Description=batty battery threshold D-Bus service\n\
+             \n\
+             [Service]\n\
+             Type=dbus\n\
+             BusName={}\n\
+             ExecStart={}\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            daemon::BUS_NAME,
+            command
+        );
+
+        let dbus_policy = format!(
+            "<!DOCTYPE busconfig PUBLIC \"-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN\"\n \
+             \"http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd\">\n\
+             <busconfig>\n  \
+                 <policy user=\"root\">\n    \
+                     <allow own=\"{name}\"/>\n  \
+                 </policy>\n  \
+                 <policy context=\"default\">\n    \
+                     <allow send_destination=\"{name}\"/>\n  \
+                 </policy>\n\
+             </busconfig>\n",
+            name = daemon::BUS_NAME
+        );
+
+        let polkit_policy = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE policyconfig PUBLIC \"-//freedesktop//DTD PolicyKit Policy Configuration 1.0//EN\"\n \
+             \"http://www.freedesktop.org/standards/PolicyKit/1/policyconfig.dtd\">\n\
+             <policyconfig>\n  \
+                 <action id=\"{}\">\n    \
+                     <description>Change battery charge thresholds</description>\n    \
+                     <message>Authentication is required to change battery charge thresholds</message>\n    \
+                     <defaults>\n      \
+                         <allow_any>auth_admin</allow_any>\n      \
+                         <allow_inactive>auth_admin</allow_inactive>\n      \
+                         <allow_active>auth_admin_keep</allow_active>\n    \
+                     </defaults>\n  \
+                 </action>\n\
+             </policyconfig>\n",
+            daemon::SET_THRESHOLDS_ACTION
+        );
+
+        Self {
+            unit,
+            dbus_policy,
+            polkit_policy,
+        }
+    }
+
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let files = [
+            (DAEMON_UNIT_PATH, &self.unit),
+            (DBUS_POLICY_PATH, &self.dbus_policy),
+            (POLKIT_POLICY_PATH, &self.polkit_policy),
+        ];
+
+        let mut paths = Vec::new();
+        for (path, contents) in files {
+            let path = PathBuf::from(path);
+            write_file(&path, contents, 0o644)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// A sysfs device whose attributes batty writes, identified the way udev matches it.
+pub struct WritableDevice {
+    pub subsystem: String,
+    pub kernel: String,
+    /// Absolute paths of the attributes to open up.
+    pub files: Vec<PathBuf>,
+}
+
+impl WritableDevice {
+    /// Groups `file` under the device directory it lives in. The subsystem is read from
+    /// the device's `subsystem` link, falling back to `power_supply` for attributes of
+    /// the battery itself and `platform` for vendor drivers.
+    pub fn add(devices: &mut Vec<Self>, battery_path: &Path, file: PathBuf) {
+        let Some(device_path) = file.parent() else {
+            return;
+        };
+        let kernel = match device_path.file_name().and_then(|n| n.to_str()) {
+            Some(kernel) => kernel.to_string(),
+            None => return,
+        };
+        let subsystem = fs::read_link(device_path.join("subsystem"))
+            .ok()
+            .and_then(|link| link.file_name()?.to_str().map(str::to_string))
+            .unwrap_or_else(|| {
+                if device_path == battery_path {
+                    "power_supply".to_string()
+                } else {
+                    "platform".to_string()
+                }
+            });
+
+        match devices
+            .iter_mut()
+            .find(|d| d.subsystem == subsystem && d.kernel == kernel)
+        {
+            Some(device) if device.files.contains(&file) => {}
+            Some(device) => device.files.push(file),
+            None => devices.push(Self {
+                subsystem,
+                kernel,
+                files: vec![file],
+            }),
+        }
+    }
+}
+
+/// udev rules and a tmpfiles.d entry granting `group` write access to the threshold
+/// attributes. udev applies the rules when the devices appear at boot; the tmpfiles
+/// entry also fixes the permissions right away. It also creates the state and the shared
+/// store as root-owned files the group may write, so root's `batty apply` restores what
+/// the group saved. The config stays root-only, since it runs `watch.hook` and picks the
+/// sysfs root that `batty apply` writes to.
+pub struct PermissionFiles {
+    pub rules: String,
+    pub tmpfiles: String,
+}
+
+impl PermissionFiles {
+    pub fn generate(devices: &[WritableDevice], group: &str) -> Self {
+        let mut rules = String::from(
+            "# Generated by batty setup-permissions: lets the group write charge thresholds.\n",
+        );
+        let mut tmpfiles = String::from("# Generated by batty setup-permissions\n");
+
+        for device in devices {
+            rules.push_str(&format!(
+                "ACTION==\"add\", SUBSYSTEM==\"{}\", KERNEL==\"{}\"",
+                device.subsystem, device.kernel
+            ));
+            for file in &device.files {
+                let Some(attribute) = file.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                rules.push_str(&format!(
+                    ", RUN+=\"/bin/chgrp {group} /sys%p/{attribute}\", RUN+=\"/bin/chmod g+w /sys%p/{attribute}\""
+                ));
+                tmpfiles.push_str(&format!("z {} 0664 root {} -\n", file.display(), group));
+            }
+            rules.push('\n');
+        }
+
+        if let Some(dir) = Path::new(DEFAULT_STATE_PATH).parent() {
+            tmpfiles.push_str(&format!("d {} 0755 root root -\n", dir.display()));
+        }
+        for file in [DEFAULT_STATE_PATH, SHARED_STORE_PATH] {
+            tmpfiles.push_str(&format!("f {} 0664 root {} -\n", file, group));
+        }
+
+        Self { rules, tmpfiles }
+    }
+
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let rules_path = PathBuf::from(UDEV_RULES_PATH);
+        let tmpfiles_path = PathBuf::from(TMPFILES_PATH);
+
+        write_file(&rules_path, &self.rules, 0o644)?;
+        write_file(&tmpfiles_path, &self.tmpfiles, 0o644)?;
+
+        Ok(vec![rules_path, tmpfiles_path])
+    }
+
+    /// Removes the installed files and returns the ones that existed.
+    pub fn uninstall() -> io::Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for path in [UDEV_RULES_PATH, TMPFILES_PATH] {
+            match fs::remove_file(path) {
+                Ok(()) => removed.push(PathBuf::from(path)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!("Failed to remove {}: {}", path, e),
+                    ))
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn write_file(path: &Path, contents: &str, mode: u32) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    })?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permissions_cover_sysfs_attributes_and_shared_data() {
+        let battery = Path::new("/sys/class/power_supply/BAT0");
+        let mut devices = Vec::new();
+        for attribute in [
+            "charge_control_start_threshold",
+            "charge_control_end_threshold",
+        ] {
+            WritableDevice::add(&mut devices, battery, battery.join(attribute));
+        }
+
+        let files = PermissionFiles::generate(&devices, "batty");
+
+        assert_eq!(
+            files.tmpfiles.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "z /sys/class/power_supply/BAT0/charge_control_start_threshold 0664 root batty -",
+                "z /sys/class/power_supply/BAT0/charge_control_end_threshold 0664 root batty -",
+                "d /var/lib/batty 0755 root root -",
+                "f /var/lib/batty/state.toml 0664 root batty -",
+                "f /var/lib/batty/thresholds.toml 0664 root batty -",
+            ]
+        );
+        assert!(files
+            .rules
+            .contains("SUBSYSTEM==\"power_supply\", KERNEL==\"BAT0\""));
+        assert!(!files.rules.contains("/etc/") && !files.rules.contains("/var/"));
+        assert!(!files.tmpfiles.contains("/etc/"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{battery::ChargeBehaviour, calibrate::Phase, thresholds::Thresholds};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_STATE_PATH: &str = "/var/lib/batty/state.toml";
+
+/// Operations that must outlive the batty process, such as a pending top-up restore.
+/// Unlike [`crate::config::Config`] this is written by batty only.
+#[derive(Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub topup: BTreeMap<String, PendingTopup>,
+    /// Thresholds batty last wrote to each battery, as accepted by the hardware. Compared
+    /// with the current values to notice firmware overriding them.
+    #[serde(default)]
+    pub applied: BTreeMap<String, Thresholds>,
+    #[serde(default)]
+    pub calibration: BTreeMap<String, PendingCalibration>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingTopup {
+    /// Thresholds to restore once the battery is full.
+    pub previous: Thresholds,
+    /// Unix timestamp after which the thresholds are restored even if the battery isn't full.
+    pub deadline: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingCalibration {
+    pub phase: Phase,
+    /// Thresholds and charge behaviour to restore once the calibration ends.
+    pub previous: Thresholds,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_behaviour: Option<ChargeBehaviour>,
+    /// `energy_full` (or `charge_full`) when the calibration started.
+    pub capacity_before: u32,
+    /// Unix timestamp of the start.
+    pub started: u64,
+}
+
+impl State {
+    /// Reads the state at `path`. A missing file yields an empty state.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid state {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Fails when a top-up or calibration of `name` is pending, since it restores its own
+    /// copy of the previous thresholds and would overwrite anything written meanwhile.
+    pub fn ensure_idle(&self, name: &str) -> io::Result<()> {
+        let pending = if self.topup.contains_key(name) {
+            "a top-up"
+        } else if self.calibration.contains_key(name) {
+            "a calibration"
+        } else {
+            return Ok(());
+        };
+
+        Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            format!("{} is pending for {}, cancel it first", pending, name),
+        ))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        // Only root may create files next to the system state. `batty setup-permissions`
+        // lets its group write the file itself, so everyone else updates it in place
+        if !is_root() && path.exists() {
+            return fs::write(path, contents);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated state behind,
+        // keeping the owner and mode of the file it replaces so the group keeps access
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+            std::os::unix::fs::chown(&tmp_path, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Remembers `thresholds` as the last ones batty applied to `name`.
+pub fn record_applied(state_path: &Path, name: &str, thresholds: &Thresholds) -> io::Result<()> {
+    let mut state = State::load(state_path)?;
+    state.applied.insert(name.to_string(), thresholds.clone());
+    state.save(state_path)
+}
+
+/// `path` when given, otherwise the system state. Everyone shares it, so that root's
+/// `batty apply` finishes top-ups and calibrations whoever started them, and a pending one
+/// blocks every user's saves.
+pub fn state_path(path: Option<PathBuf>) -> PathBuf {
+    path.unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_PATH))
+}
+
+pub fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn saving_keeps_the_group_access() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.toml");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o664)).unwrap();
+
+        let mut state = State::default();
+        state
+            .applied
+            .insert("BAT0".to_string(), Thresholds { start: 40, end: 80 });
+        state.save(&path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o664);
+        assert!(State::load(&path).unwrap().applied.contains_key("BAT0"));
+    }
+}
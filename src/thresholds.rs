@@ -1,11 +1,16 @@
+use crate::{
+    backend::{Capability, ThresholdBackend},
+    profile::DEFAULT_THRESHOLDS,
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::{
-    fmt,
-    fs,
-    io,
+    fmt, fs, io,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, ValueEnum)]
 pub enum ThresholdKind {
     Start,
     End,
@@ -20,36 +25,29 @@ impl fmt::Display for ThresholdKind {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Thresholds {
     pub start: u8,
     pub end: u8,
 }
 
 impl Thresholds {
-    pub fn load(base_path: &Path) -> io::Result<Self> {
-        let start_path = get_path_for_kind(base_path, &ThresholdKind::Start);
-        let end_path = get_path_for_kind(base_path, &ThresholdKind::End);
-
-        let start = match read_threshold(&start_path) {
-            Ok(value) => value,
-            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
-            Err(err) => return Err(err),
-        };
-        let end = read_threshold(&end_path)?;
-
-        Ok(Self { start, end })
+    pub fn load(base_path: &Path, backend: &dyn ThresholdBackend) -> io::Result<Self> {
+        backend.load(base_path)
     }
 
-    pub fn save(&self, base_path: &Path) -> io::Result<()> {
-        let start_path = get_path_for_kind(base_path, &ThresholdKind::Start);
-        let end_path = get_path_for_kind(base_path, &ThresholdKind::End);
+    /// Writes the thresholds and reads them back, since firmware may silently clamp or
+    /// ignore values it doesn't support.
+    pub fn save(&self, base_path: &Path, backend: &dyn ThresholdBackend) -> io::Result<SaveReport> {
+        backend.save(base_path, self)?;
+        let accepted = backend.load(base_path)?;
 
-        if start_path.exists() {
-            write_threshold(&start_path, self.start)?;
-        }
-        write_threshold(&end_path, self.end)?;
-
-        Ok(())
+        Ok(SaveReport::new(
+            self.clone(),
+            accepted,
+            backend.capability(base_path),
+        ))
     }
 
     pub fn get(&self, kind: ThresholdKind) -> u8 {
@@ -81,11 +79,122 @@ impl Thresholds {
 
         Ok(())
     }
+
+    /// Replaces the given values and checks the resulting pair as a whole, unlike
+    /// [`Thresholds::set`], so 40-80% can become 85-95% in one step.
+    pub fn with(&self, start: Option<u8>, end: Option<u8>) -> Result<Self, String> {
+        let thresholds = Self {
+            start: start.unwrap_or(self.start),
+            end: end.unwrap_or(self.end),
+        };
+        thresholds.validate()?;
+        Ok(thresholds)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for kind in [ThresholdKind::Start, ThresholdKind::End] {
+            if self.get(kind) > 100 {
+                return Err(format!(
+                    "{} threshold ({}%) must be between 0 and 100",
+                    kind,
+                    self.get(kind)
+                ));
+            }
+        }
+        if self.start >= self.end {
+            return Err(format!(
+                "start threshold ({}%) must be less than end threshold ({}%)",
+                self.start, self.end
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `START-END`, e.g. `85-95` or `85%-95%`. Only the syntax is checked; see
+/// [`Thresholds::validate`].
+impl FromStr for Thresholds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = |value: &str| {
+            value
+                .trim()
+                .trim_end_matches('%')
+                .parse::<u8>()
+                .map_err(|_| format!("invalid threshold {:?}, expected START-END like 40-80", s))
+        };
+
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid range {:?}, expected START-END like 40-80", s))?;
+
+        Ok(Self {
+            start: percent(start)?,
+            end: percent(end)?,
+        })
+    }
 }
 
 impl Default for Thresholds {
     fn default() -> Self {
-        Self { start: 40, end: 80 }
+        DEFAULT_THRESHOLDS
+    }
+}
+
+/// Outcome of [`Thresholds::save`]: what was asked for and what the hardware now reports.
+pub struct SaveReport {
+    pub requested: Thresholds,
+    pub accepted: Thresholds,
+    /// Kinds whose read-back value differs from the requested one.
+    pub clamped: Vec<ThresholdKind>,
+}
+
+impl SaveReport {
+    fn new(requested: Thresholds, accepted: Thresholds, capability: Capability) -> Self {
+        // Backends without a writable start report whatever the firmware uses, so only
+        // compare the values that were actually written.
+        let kinds: &[ThresholdKind] = match capability {
+            Capability::StartAndEnd => &[ThresholdKind::Start, ThresholdKind::End],
+            Capability::EndOnly | Capability::OnOff => &[ThresholdKind::End],
+        };
+
+        let clamped = kinds
+            .iter()
+            .copied()
+            .filter(|kind| match capability {
+                // Any end below 100% turns the switch on, which caps the charge at the
+                // backend's own level, so only the switch can disagree
+                Capability::OnOff => (requested.end < 100) != (accepted.end < 100),
+                _ => requested.get(*kind) != accepted.get(*kind),
+            })
+            .collect();
+
+        Self {
+            requested,
+            accepted,
+            clamped,
+        }
+    }
+
+    pub fn is_clamped(&self) -> bool {
+        !self.clamped.is_empty()
+    }
+
+    /// One line per clamped kind, e.g. "end threshold clamped from 85% to 80%".
+    pub fn clamp_messages(&self) -> Vec<String> {
+        self.clamped
+            .iter()
+            .map(|kind| {
+                format!(
+                    "{} threshold clamped from {}% to {}%",
+                    kind,
+                    self.requested.get(*kind),
+                    self.accepted.get(*kind)
+                )
+            })
+            .collect()
     }
 }
 
@@ -96,7 +205,7 @@ pub fn get_path_for_kind(base_path: &Path, kind: &ThresholdKind) -> PathBuf {
     }
 }
 
-fn read_threshold(path: &Path) -> io::Result<u8> {
+pub(crate) fn read_threshold(path: &Path) -> io::Result<u8> {
     let current = fs::read_to_string(path)?;
     let trimmed = current.trim();
     trimmed.parse::<u8>().map_err(|_| {
@@ -107,6 +216,103 @@ fn read_threshold(path: &Path) -> io::Result<u8> {
     })
 }
 
-fn write_threshold(path: &Path, value: u8) -> io::Result<()> {
+pub(crate) fn write_threshold(path: &Path, value: u8) -> io::Result<()> {
     fs::write(path, value.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURRENT: Thresholds = Thresholds { start: 40, end: 80 };
+
+    #[test]
+    fn with_checks_the_pair_as_a_whole() {
+        // Both values move past the current end in one step
+        assert_eq!(
+            CURRENT.with(Some(85), Some(95)),
+            Ok(Thresholds { start: 85, end: 95 })
+        );
+        assert_eq!(
+            CURRENT.with(Some(95), Some(85)),
+            Err("start threshold (95%) must be less than end threshold (85%)".to_string())
+        );
+        assert_eq!(
+            CURRENT.with(Some(90), Some(90)),
+            Err("start threshold (90%) must be less than end threshold (90%)".to_string())
+        );
+        assert_eq!(
+            CURRENT.with(Some(50), Some(101)),
+            Err("end threshold (101%) must be between 0 and 100".to_string())
+        );
+    }
+
+    #[test]
+    fn with_keeps_the_value_not_given() {
+        assert_eq!(
+            CURRENT.with(Some(60), None),
+            Ok(Thresholds { start: 60, end: 80 })
+        );
+        assert_eq!(
+            CURRENT.with(None, Some(100)),
+            Ok(Thresholds {
+                start: 40,
+                end: 100
+            })
+        );
+        assert_eq!(CURRENT.with(None, None), Ok(CURRENT));
+        // Checked against the current other value
+        assert!(CURRENT.with(Some(80), None).is_err());
+        assert!(CURRENT.with(None, Some(30)).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_swapped_and_equal_values() {
+        assert_eq!(CURRENT.validate(), Ok(()));
+        assert_eq!(Thresholds { start: 0, end: 100 }.validate(), Ok(()));
+        assert_eq!(
+            Thresholds {
+                start: 101,
+                end: 102
+            }
+            .validate(),
+            Err("start threshold (101%) must be between 0 and 100".to_string())
+        );
+        assert!(Thresholds { start: 80, end: 40 }.validate().is_err());
+        assert!(Thresholds { start: 60, end: 60 }.validate().is_err());
+    }
+
+    #[test]
+    fn on_off_backends_only_report_a_switch_that_did_not_flip() {
+        let report = |capability, accepted| {
+            SaveReport::new(Thresholds { start: 0, end: 85 }, accepted, capability)
+        };
+
+        assert!(!report(Capability::OnOff, Thresholds { start: 0, end: 60 }).is_clamped());
+        assert_eq!(
+            report(Capability::OnOff, Thresholds { start: 0, end: 100 }).clamp_messages(),
+            ["end threshold clamped from 85% to 100%"]
+        );
+        assert!(report(Capability::EndOnly, Thresholds { start: 0, end: 80 }).is_clamped());
+        assert!(!report(Capability::EndOnly, Thresholds { start: 40, end: 85 }).is_clamped());
+    }
+
+    #[test]
+    fn parses_ranges() {
+        for range in ["85-95", "85%-95%", " 85 - 95 "] {
+            assert_eq!(
+                range.parse(),
+                Ok(Thresholds { start: 85, end: 95 }),
+                "{}",
+                range
+            );
+        }
+        // Only the syntax is checked
+        assert_eq!("95-85".parse(), Ok(Thresholds { start: 95, end: 85 }));
+        assert_eq!("90-90".parse(), Ok(Thresholds { start: 90, end: 90 }));
+
+        for range in ["85", "85-", "-95", "a-95", "85-300", "85--95"] {
+            assert!(range.parse::<Thresholds>().is_err(), "{}", range);
+        }
+    }
+}
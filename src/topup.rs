@@ -0,0 +1,132 @@
+use crate::{
+    backend::ThresholdBackend,
+    battery::{battery_name, Battery, BatteryStatus},
+    state::{self, PendingTopup, State},
+    thresholds::{SaveReport, Thresholds},
+};
+use std::{io, path::Path, time::Duration};
+
+/// Thresholds used while topping up. Start is raised too, since most firmware won't resume
+/// charging until the charge drops below it.
+pub const TOPUP_THRESHOLDS: Thresholds = Thresholds {
+    start: 95,
+    end: 100,
+};
+
+pub const DEFAULT_TIMEOUT_HOURS: u64 = 12;
+/// Longest accepted `--timeout`, 30 days.
+pub const MAX_TIMEOUT_HOURS: u64 = 720;
+
+/// Charge at which a battery that doesn't report [`BatteryStatus::Full`] is considered topped up.
+const FULL_CHARGE: f32 = 99.0;
+
+/// Raises the thresholds of one battery to [`TOPUP_THRESHOLDS`]. The previous thresholds
+/// are recorded in the state file before anything is written, so they can still be
+/// restored if batty is killed.
+pub fn start(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+    timeout: Duration,
+) -> io::Result<SaveReport> {
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+
+    if state.topup.contains_key(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("a top-up is already pending for {}", name),
+        ));
+    }
+    if state.calibration.contains_key(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is being calibrated", name),
+        ));
+    }
+
+    let previous = Thresholds::load(battery_path, backend)?;
+    state.topup.insert(
+        name.to_string(),
+        PendingTopup {
+            previous,
+            deadline: state::now().saturating_add(timeout.as_secs()),
+        },
+    );
+    state.save(state_path)?;
+
+    match TOPUP_THRESHOLDS.save(battery_path, backend) {
+        Ok(report) => {
+            state
+                .applied
+                .insert(name.to_string(), report.accepted.clone());
+            state.save(state_path)?;
+            Ok(report)
+        }
+        Err(err) => {
+            state.topup.remove(name);
+            state.save(state_path)?;
+            Err(err)
+        }
+    }
+}
+
+/// Restores the thresholds recorded for a pending top-up and clears it.
+/// Returns `None` if no top-up is pending for the battery.
+pub fn restore(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<Option<SaveReport>> {
+    let name = battery_name(battery_path);
+    let mut state = State::load(state_path)?;
+
+    let Some(pending) = state.topup.get(name) else {
+        return Ok(None);
+    };
+
+    let report = pending.previous.save(battery_path, backend)?;
+    state.topup.remove(name);
+    state
+        .applied
+        .insert(name.to_string(), report.accepted.clone());
+    state.save(state_path)?;
+
+    Ok(Some(report))
+}
+
+/// Restores the previous thresholds if the battery is full or the top-up timed out.
+pub fn restore_if_complete(
+    battery_path: &Path,
+    backend: &dyn ThresholdBackend,
+    state_path: &Path,
+) -> io::Result<Option<SaveReport>> {
+    let state = State::load(state_path)?;
+
+    match state.topup.get(battery_name(battery_path)) {
+        Some(pending) if is_complete(battery_path, pending) => {
+            restore(battery_path, backend, state_path)
+        }
+        _ => Ok(None),
+    }
+}
+
+pub fn pending(battery_path: &Path, state_path: &Path) -> io::Result<Option<PendingTopup>> {
+    let state = State::load(state_path)?;
+    Ok(state.topup.get(battery_name(battery_path)).cloned())
+}
+
+pub fn is_complete(battery_path: &Path, pending: &PendingTopup) -> bool {
+    if state::now() >= pending.deadline {
+        return true;
+    }
+
+    match Battery::new(battery_path) {
+        Ok((battery, _)) => is_full(&battery),
+        Err(_) => false,
+    }
+}
+
+pub fn is_full(battery: &Battery) -> bool {
+    battery.status == BatteryStatus::Full || battery.charge_percentage() >= FULL_CHARGE
+}
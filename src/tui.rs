@@ -1,6 +1,16 @@
 use crate::{
-    battery::Battery,
+    backend::{Backends, Capability, ThresholdBackend},
+    battery::{battery_name, format_duration, Battery, BatteryStatus, ChargeBehaviour, ChargeType},
+    calibrate::{self, Finished, Phase, Progress},
+    config::{self, Config, Problem},
+    device::{self, Device},
+    drift::{self, Drift},
+    history::{self, History},
+    power_source::{self, PowerSource},
+    profile::Profile,
+    state::{self, PendingCalibration, State},
     thresholds::{ThresholdKind, Thresholds},
+    topup,
 };
 use crossterm::{
     event::{self, Event, KeyCode},
@@ -9,20 +19,385 @@ use crossterm::{
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Flex, Layout},
+    layout::{Alignment, Constraint, Direction, Flex, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols::Marker,
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Tabs},
+    widgets::{
+        block::{Position, Title},
+        Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, List, ListItem, ListState,
+        Paragraph, Row, Sparkline, Table, Tabs,
+    },
     Frame, Terminal,
 };
-use std::{io, path::PathBuf, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+const TOPUP_TIMEOUT: Duration = Duration::from_secs(topup::DEFAULT_TIMEOUT_HOURS * 60 * 60);
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const CHARGE_HISTORY_HOURS: u64 = 24;
+const HEALTH_HISTORY_DAYS: u64 = 365;
+const HISTORY_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+const POWER_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+const POWER_WINDOW: Duration = Duration::from_secs(5 * 60);
 
 type BattyBackend = CrosstermBackend<io::Stdout>;
 type BattyTerminal = Terminal<BattyBackend>;
 
-pub fn run_tui(bat_paths: Vec<PathBuf>) -> io::Result<()> {
+/// The `[tui]` section of the config file.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TuiConfig {
+    /// Milliseconds between redraws, which is also how often the battery is read.
+    pub refresh_ms: u64,
+    pub theme: Theme,
+    pub keys: KeyBindings,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            refresh_ms: 250,
+            theme: Theme::default(),
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+impl TuiConfig {
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        if self.refresh_ms == 0 {
+            problems.push(Problem::new(&["tui.refresh-ms"], "must be at least 1"));
+        }
+
+        let bindings = self.keys.bindings();
+        for (i, (action, keys)) in bindings.iter().enumerate() {
+            for key in *keys {
+                if let Some((other, _)) = bindings[i + 1..]
+                    .iter()
+                    .find(|(_, other_keys)| other_keys.contains(key))
+                {
+                    problems.push(Problem::new(
+                        &[
+                            &format!("tui.keys.{}", action),
+                            &format!("tui.keys.{}", other),
+                        ],
+                        format!("{} is bound to both {} and {}", key.name(), action, other),
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// Colours of the TUI by role. Each is a colour name like `light-blue`, an ANSI index
+/// or `#rrggbb`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Selected tabs, rows and list entries.
+    pub accent: ThemeColor,
+    /// Confirmations and a charging battery.
+    pub ok: ThemeColor,
+    /// Warnings, a discharging battery and inhibited charging.
+    pub warning: ThemeColor,
+    /// Errors and forced discharging.
+    pub error: ThemeColor,
+    /// Progress of top-ups and calibrations and a battery held by a threshold.
+    pub info: ThemeColor,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            accent: ThemeColor(Color::Yellow),
+            ok: ThemeColor(Color::Green),
+            warning: ThemeColor(Color::Yellow),
+            error: ThemeColor(Color::Red),
+            info: ThemeColor(Color::Cyan),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ThemeColor(Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Color::from_str(&name)
+            .map(Self)
+            .map_err(|_| format!("unknown colour {}", name))
+    }
+}
+
+impl From<ThemeColor> for String {
+    fn from(color: ThemeColor) -> Self {
+        color.0.to_string().to_lowercase()
+    }
+}
+
+/// A key in the `[tui.keys]` section: a single character, or one of `up`, `down`,
+/// `left`, `right`, `enter`, `esc`, `tab`, `backspace` and `space`.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(KeyCode);
+
+impl Key {
+    /// How the key is shown in the TUI's help.
+    fn name(&self) -> String {
+        match self.0 {
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            _ => "?".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let code = match name.to_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "space" => KeyCode::Char(' '),
+            _ => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("unknown key {}", name)),
+                }
+            }
+        };
+        Ok(Self(code))
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        match key.0 {
+            KeyCode::Up => "up".to_string(),
+            KeyCode::Down => "down".to_string(),
+            KeyCode::Left => "left".to_string(),
+            KeyCode::Right => "right".to_string(),
+            KeyCode::Enter => "enter".to_string(),
+            KeyCode::Esc => "esc".to_string(),
+            KeyCode::Tab => "tab".to_string(),
+            KeyCode::Backspace => "backspace".to_string(),
+            KeyCode::Char(' ') => "space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn join_keys(keys: &[Key], separator: &str) -> String {
+    keys.iter()
+        .map(Key::name)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn keys(names: &[&str]) -> Vec<Key> {
+    names
+        .iter()
+        .filter_map(|name| Key::try_from(name.to_string()).ok())
+        .collect()
+}
+
+/// What a key does in the battery view.
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Quit,
+    Increase,
+    Decrease,
+    Save,
+    NextSetting,
+    PreviousSetting,
+    PreviousTab,
+    NextTab,
+    Profiles,
+    Topup,
+    History,
+    Calibrate,
+    CancelCalibration,
+    Reapply,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Quit => "quit",
+            Self::Increase => "increase",
+            Self::Decrease => "decrease",
+            Self::Save => "save",
+            Self::NextSetting => "next-setting",
+            Self::PreviousSetting => "previous-setting",
+            Self::PreviousTab => "previous-tab",
+            Self::NextTab => "next-tab",
+            Self::Profiles => "profiles",
+            Self::Topup => "topup",
+            Self::History => "history",
+            Self::Calibrate => "calibrate",
+            Self::CancelCalibration => "cancel-calibration",
+            Self::Reapply => "reapply",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The `[tui.keys]` section: the keys bound to each action.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct KeyBindings {
+    pub quit: Vec<Key>,
+    pub increase: Vec<Key>,
+    pub decrease: Vec<Key>,
+    pub save: Vec<Key>,
+    pub next_setting: Vec<Key>,
+    pub previous_setting: Vec<Key>,
+    pub previous_tab: Vec<Key>,
+    pub next_tab: Vec<Key>,
+    pub profiles: Vec<Key>,
+    pub topup: Vec<Key>,
+    pub history: Vec<Key>,
+    pub calibrate: Vec<Key>,
+    pub cancel_calibration: Vec<Key>,
+    pub reapply: Vec<Key>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            quit: keys(&["q", "esc"]),
+            increase: keys(&["up", "+"]),
+            decrease: keys(&["down", "-"]),
+            save: keys(&["enter"]),
+            next_setting: keys(&["j"]),
+            previous_setting: keys(&["k"]),
+            previous_tab: keys(&["left", "["]),
+            next_tab: keys(&["right", "]"]),
+            profiles: keys(&["p"]),
+            topup: keys(&["t"]),
+            history: keys(&["h"]),
+            calibrate: keys(&["c"]),
+            cancel_calibration: keys(&["x"]),
+            reapply: keys(&["r"]),
+        }
+    }
+}
+
+impl KeyBindings {
+    fn bindings(&self) -> [(Action, &[Key]); 14] {
+        [
+            (Action::Quit, &self.quit),
+            (Action::Increase, &self.increase),
+            (Action::Decrease, &self.decrease),
+            (Action::Save, &self.save),
+            (Action::NextSetting, &self.next_setting),
+            (Action::PreviousSetting, &self.previous_setting),
+            (Action::PreviousTab, &self.previous_tab),
+            (Action::NextTab, &self.next_tab),
+            (Action::Profiles, &self.profiles),
+            (Action::Topup, &self.topup),
+            (Action::History, &self.history),
+            (Action::Calibrate, &self.calibrate),
+            (Action::CancelCalibration, &self.cancel_calibration),
+            (Action::Reapply, &self.reapply),
+        ]
+    }
+
+    fn keys(&self, action: Action) -> &[Key] {
+        self.bindings()
+            .into_iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, keys)| keys)
+            .unwrap_or_default()
+    }
+
+    fn action(&self, code: KeyCode) -> Option<Action> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, keys)| keys.contains(&Key(code)))
+            .map(|(action, _)| action)
+    }
+
+    fn is(&self, action: Action, code: KeyCode) -> bool {
+        self.action(code) == Some(action)
+    }
+
+    /// Help label for one action, e.g. "h".
+    fn label(&self, action: Action) -> String {
+        join_keys(self.keys(action), "/")
+    }
+
+    /// Help label for two opposite actions, e.g. "↑/↓ or +/-".
+    fn pair_label(&self, first: Action, second: Action) -> String {
+        let (first, second) = (self.keys(first), self.keys(second));
+
+        if first.len() != second.len() {
+            return format!("{}/{}", join_keys(first, ","), join_keys(second, ","));
+        }
+        first
+            .iter()
+            .zip(second)
+            .map(|(a, b)| format!("{}/{}", a.name(), b.name()))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+}
+
+pub fn run_tui(
+    bat_paths: Vec<PathBuf>,
+    show_devices: bool,
+    config: &Config,
+    config_path: PathBuf,
+    state_path: PathBuf,
+    history_dir: PathBuf,
+    backends: Backends,
+) -> io::Result<()> {
+    let mut app = App::new(
+        bat_paths,
+        show_devices,
+        config,
+        config_path,
+        state_path,
+        history_dir,
+        backends,
+    )?;
+
     let mut terminal = setup_terminal()?;
-    let result = run_app(&mut terminal, bat_paths);
+    let result = run_app(&mut terminal, &mut app);
     restore_terminal(&mut terminal)?;
     result
 }
@@ -42,65 +417,203 @@ fn restore_terminal(terminal: &mut BattyTerminal) -> io::Result<()> {
     Ok(())
 }
 
-fn run_app(terminal: &mut BattyTerminal, bat_paths: Vec<PathBuf>) -> io::Result<()> {
-    let mut app = App::new(bat_paths)?;
+fn run_app(terminal: &mut BattyTerminal, app: &mut App) -> io::Result<()> {
+    let refresh = Duration::from_millis(app.settings.refresh_ms.max(1));
+    let mut last_tick: Option<Instant> = None;
 
     loop {
-        terminal.draw(|frame| draw_ui(frame, &mut app))?;
+        // Key presses redraw immediately, but sysfs and the state file are only read once
+        // per refresh interval.
+        if last_tick.is_none_or(|at| at.elapsed() >= refresh) {
+            app.tick();
+            last_tick = Some(Instant::now());
+        }
 
-        if event::poll(Duration::from_millis(250))? {
+        terminal.draw(|frame| draw_ui(frame, app))?;
+
+        let timeout = last_tick.map_or(refresh, |at| refresh.saturating_sub(at.elapsed()));
+        if event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
-                    KeyCode::Up | KeyCode::Char('+') => app.increment(),
-                    KeyCode::Down | KeyCode::Char('-') => app.decrement(),
-                    KeyCode::Enter => app.save(),
-                    KeyCode::Char('j') | KeyCode::Char('k') => app.select_next_threshold_kind(),
-                    KeyCode::Left | KeyCode::Char('[') => app.prev_tab(),
-                    KeyCode::Right | KeyCode::Char(']') => app.next_tab(),
-                    _ => {}
+                let keys = &app.settings.keys;
+
+                if app.profile_picker.is_some() {
+                    match key.code {
+                        KeyCode::Esc => app.profile_picker = None,
+                        KeyCode::Up => app.move_profile_selection(-1),
+                        KeyCode::Down => app.move_profile_selection(1),
+                        KeyCode::Enter => app.load_selected_profile(),
+                        code if keys.is(Action::Profiles, code) => app.profile_picker = None,
+                        code if keys.is(Action::PreviousSetting, code) => {
+                            app.move_profile_selection(-1)
+                        }
+                        code if keys.is(Action::NextSetting, code) => app.move_profile_selection(1),
+                        code if keys.is(Action::Save, code) => app.load_selected_profile(),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                let Some(action) = keys.action(key.code) else {
+                    continue;
+                };
+                match action {
+                    Action::Quit => return Ok(()),
+                    Action::Increase => app.increment(),
+                    Action::Decrease => app.decrement(),
+                    Action::Save if app.calibration_view => app.start_calibration(),
+                    Action::Save => app.save(),
+                    Action::CancelCalibration if app.calibration_view => app.abort_calibration(),
+                    Action::CancelCalibration => {}
+                    Action::NextSetting => app.select_setting(1),
+                    Action::PreviousSetting => app.select_setting(-1),
+                    Action::PreviousTab => app.prev_tab(),
+                    Action::NextTab => app.next_tab(),
+                    Action::Profiles => app.open_profile_picker(),
+                    Action::Topup => app.toggle_topup(),
+                    Action::History => app.toggle_history(),
+                    Action::Calibrate => app.toggle_calibration(),
+                    Action::Reapply => app.reapply(),
                 }
             }
         }
     }
 }
 
+/// Editable rows of the Threshold Configuration panel.
+#[derive(Clone, Copy, PartialEq)]
+enum Setting {
+    Threshold(ThresholdKind),
+    ChargeBehaviour,
+}
+
 struct App {
+    settings: TuiConfig,
     battery: Battery,
+    power_supply_path: PathBuf,
+    power_sources: Vec<PowerSource>,
     bat_paths: Vec<PathBuf>,
     base_path: PathBuf,
+    config_path: PathBuf,
+    state_path: PathBuf,
+    backends: Backends,
+    backend: Box<dyn ThresholdBackend>,
+    /// Whether the read-only Devices tab follows the battery tabs.
+    show_devices: bool,
+    devices: Vec<Device>,
+    topup_pending: bool,
+    calibration: Option<PendingCalibration>,
+    /// Outcome of a calibration finished while the TUI was open.
+    calibration_result: Option<Finished>,
+    calibration_view: bool,
+    history: History,
+    history_view: Option<HistoryView>,
+    /// Power draw in mW sampled over the last [`POWER_WINDOW`], oldest first.
+    power_samples: VecDeque<(Instant, u64)>,
     selected_tab: usize,
-    curr_threshold_kind: ThresholdKind,
+    selected_setting: Setting,
     thresholds: Thresholds,
+    /// Charge behaviour being edited and the ones the battery accepts, empty when it
+    /// has no `charge_behaviour` attribute.
+    charge_behaviour: Option<ChargeBehaviour>,
+    charge_behaviours: Vec<ChargeBehaviour>,
+    /// Thresholds currently on the hardware, as opposed to the ones being edited.
+    applied: Option<Thresholds>,
+    /// Set when the hardware thresholds no longer match the ones batty last applied.
+    drift: Option<Drift>,
+    profiles: Vec<Profile>,
+    profile_picker: Option<usize>,
     status: Option<String>,
     error: Option<String>,
     warnings: Vec<String>,
 }
 
 impl App {
-    fn new(bat_paths: Vec<PathBuf>) -> io::Result<Self> {
+    fn new(
+        bat_paths: Vec<PathBuf>,
+        show_devices: bool,
+        config: &Config,
+        config_path: PathBuf,
+        state_path: PathBuf,
+        history_dir: PathBuf,
+        backends: Backends,
+    ) -> io::Result<Self> {
+        let power_supply_path = config.power_supply_path();
         let initial_path = bat_paths[0].clone();
-        let thresholds = Thresholds::load(&initial_path).unwrap_or_default();
+        let backend = backends.for_battery(&initial_path);
+        let applied = Thresholds::load(&initial_path, backend.as_ref()).ok();
+        let thresholds = applied.clone().unwrap_or_default();
         let (battery, warnings) = Battery::new(&initial_path)?;
+        let selected_setting =
+            Setting::Threshold(default_threshold_kind(backend.capability(&initial_path)));
+        let charge_behaviour = ChargeBehaviour::load(&initial_path).ok();
+        let charge_behaviours = ChargeBehaviour::available(&initial_path);
+        let topup_pending = matches!(topup::pending(&initial_path, &state_path), Ok(Some(_)));
+        let calibration = calibrate::pending(&initial_path, &state_path)
+            .ok()
+            .flatten();
 
         Ok(Self {
+            settings: config.tui.clone().unwrap_or_default(),
             battery,
-            curr_threshold_kind: ThresholdKind::Start,
+            power_sources: power_source::find_power_sources(&power_supply_path),
+            power_supply_path,
+            selected_setting,
             base_path: initial_path,
             bat_paths,
+            config_path,
+            state_path,
+            backends,
+            backend,
+            show_devices,
+            devices: Vec::new(),
+            topup_pending,
+            calibration,
+            calibration_result: None,
+            calibration_view: false,
+            history: History::new(history_dir),
+            history_view: None,
+            power_samples: VecDeque::new(),
             selected_tab: 0,
             thresholds,
+            charge_behaviour,
+            charge_behaviours,
+            applied,
+            drift: None,
+            profiles: Profile::all(config),
+            profile_picker: None,
             status: None,
             error: None,
             warnings,
         })
     }
 
+    fn on_devices_tab(&self) -> bool {
+        self.show_devices && self.selected_tab == self.bat_paths.len()
+    }
+
+    /// Reports an error and returns true when the Devices tab is selected.
+    fn refuse_on_devices_tab(&mut self) -> bool {
+        let refused = self.on_devices_tab();
+        if refused {
+            self.error = Some("Peripheral batteries are read-only".to_string());
+            self.status = None;
+        }
+        refused
+    }
+
     fn increment(&mut self) {
-        let current = self.thresholds.get(self.curr_threshold_kind);
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        let kind = match self.selected_setting {
+            Setting::Threshold(kind) => kind,
+            Setting::ChargeBehaviour => return self.cycle_charge_behaviour(1),
+        };
+        let current = self.thresholds.get(kind);
         let new_val = if current < 100 { current + 1 } else { current };
 
-        match self.thresholds.set(self.curr_threshold_kind, new_val) {
+        match self.thresholds.set(kind, new_val) {
             Ok(_) => {
                 self.status = None;
                 self.error = None;
@@ -112,10 +625,18 @@ impl App {
     }
 
     fn decrement(&mut self) {
-        let current = self.thresholds.get(self.curr_threshold_kind);
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        let kind = match self.selected_setting {
+            Setting::Threshold(kind) => kind,
+            Setting::ChargeBehaviour => return self.cycle_charge_behaviour(-1),
+        };
+        let current = self.thresholds.get(kind);
         let new_val = current.saturating_sub(1);
 
-        match self.thresholds.set(self.curr_threshold_kind, new_val) {
+        match self.thresholds.set(kind, new_val) {
             Ok(_) => {
                 self.status = None;
                 self.error = None;
@@ -126,87 +647,620 @@ impl App {
         }
     }
 
+    /// Steps through the behaviours the battery accepts, wrapping around.
+    fn cycle_charge_behaviour(&mut self, delta: isize) {
+        let count = self.charge_behaviours.len() as isize;
+        if count == 0 {
+            return;
+        }
+
+        let current = self
+            .charge_behaviour
+            .and_then(|b| self.charge_behaviours.iter().position(|c| *c == b))
+            .unwrap_or(0) as isize;
+        self.charge_behaviour =
+            Some(self.charge_behaviours[(current + delta).rem_euclid(count) as usize]);
+        self.status = None;
+        self.error = None;
+    }
+
     fn save(&mut self) {
-        match self.thresholds.save(&self.base_path) {
-            Ok(_) => {
-                self.status = Some(format!(
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        // A pending top-up or calibration would later overwrite the new thresholds
+        if let Err(err) = State::load(&self.state_path)
+            .and_then(|state| state.ensure_idle(battery_name(&self.base_path)))
+        {
+            self.error = Some(format!("Thresholds not saved: {}", err));
+            self.status = None;
+            return;
+        }
+
+        match self.thresholds.save(&self.base_path, self.backend.as_ref()) {
+            Ok(report) => {
+                let mut status = format!(
                     "Battery thresholds set to {}%-{}%",
-                    self.thresholds.start, self.thresholds.end
-                ));
+                    report.accepted.start, report.accepted.end
+                );
+                if report.is_clamped() {
+                    status.push_str(&format!(" ({})", report.clamp_messages().join(", ")));
+                }
+
+                self.applied = Some(report.accepted.clone());
+                self.thresholds = report.accepted;
+                self.status = Some(status);
                 self.error = None;
             }
             Err(err) => {
                 self.error = Some(format!("Failed to save thresholds: {}", err));
                 self.status = None;
+                return;
             }
         }
+
+        let name = battery_name(&self.base_path);
+
+        if let Err(err) = config::persist_thresholds(&self.config_path, name, &self.thresholds) {
+            self.error = Some(format!(
+                "Thresholds applied but {}",
+                config::store_error(&self.config_path, &err)
+            ));
+        }
+        if let Err(err) = state::record_applied(&self.state_path, name, &self.thresholds) {
+            self.error = Some(format!(
+                "Thresholds applied but not recorded in {}: {}",
+                self.state_path.display(),
+                err
+            ));
+        }
+
+        self.save_charge_behaviour();
     }
 
-    fn select_next_threshold_kind(&mut self) {
-        match self.curr_threshold_kind {
-            ThresholdKind::Start => self.curr_threshold_kind = ThresholdKind::End,
-            ThresholdKind::End => self.curr_threshold_kind = ThresholdKind::Start,
+    /// Writes the edited charge behaviour if it differs from the battery's.
+    fn save_charge_behaviour(&mut self) {
+        let Some(behaviour) = self.charge_behaviour else {
+            return;
+        };
+        if ChargeBehaviour::load(&self.base_path).ok() == Some(behaviour) {
+            return;
+        }
+
+        match behaviour.save(&self.base_path) {
+            Ok(()) => {
+                if let Some(status) = &mut self.status {
+                    status.push_str(&format!(", charge behaviour {}", behaviour.as_str()));
+                }
+            }
+            Err(err) => {
+                self.error = Some(format!("Failed to set charge behaviour: {}", err));
+                self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+            }
         }
     }
 
-    fn next_tab(&mut self) {
-        if self.selected_tab < self.bat_paths.len() - 1 {
-            self.selected_tab += 1;
-            self.base_path = self.bat_paths[self.selected_tab].clone();
-            self.thresholds = Thresholds::load(&self.base_path).unwrap_or_default();
-
-            match Battery::new(&self.base_path) {
-                Ok((battery, warnings)) => {
-                    self.battery = battery;
-                    self.warnings = warnings;
-                    self.status = None;
-                    self.error = None;
+    fn open_profile_picker(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        if !self.profiles.is_empty() {
+            self.profile_picker = Some(0);
+        }
+    }
+
+    fn move_profile_selection(&mut self, delta: isize) {
+        if let Some(selected) = self.profile_picker {
+            let last = self.profiles.len() as isize - 1;
+            self.profile_picker = Some((selected as isize + delta).clamp(0, last) as usize);
+        }
+    }
+
+    /// Loads the chosen preset into the editor; it is only written once the user saves.
+    fn load_selected_profile(&mut self) {
+        let Some(selected) = self.profile_picker.take() else {
+            return;
+        };
+        let profile = &self.profiles[selected];
+
+        self.thresholds = profile.thresholds.clone();
+        self.status = Some(format!(
+            "Loaded profile {} ({}%-{}%). Press Enter to save.",
+            profile.name, profile.thresholds.start, profile.thresholds.end
+        ));
+        self.error = None;
+    }
+
+    /// Starts a one-off charge to 100%, or cancels the pending one and restores the thresholds.
+    fn toggle_topup(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        let result = if self.topup_pending {
+            topup::restore(&self.base_path, self.backend.as_ref(), &self.state_path)
+        } else {
+            topup::start(
+                &self.base_path,
+                self.backend.as_ref(),
+                &self.state_path,
+                TOPUP_TIMEOUT,
+            )
+            .map(Some)
+        };
+
+        match result {
+            Ok(report) => {
+                self.topup_pending = !self.topup_pending;
+                if let Some(report) = report {
+                    self.applied = Some(report.accepted.clone());
+                    self.thresholds = report.accepted;
                 }
-                Err(e) => {
-                    self.error = Some(format!("Failed to load battery: {}", e));
-                    self.status = None;
-                    self.warnings.clear();
+                self.status = Some(if self.topup_pending {
+                    "Topping up to 100%. Thresholds are restored when full.".to_string()
+                } else {
+                    format!(
+                        "Top-up cancelled, thresholds restored to {}%-{}%",
+                        self.thresholds.start, self.thresholds.end
+                    )
+                });
+                self.error = None;
+            }
+            Err(err) => {
+                self.error = Some(format!("Top-up failed: {}", err));
+                self.status = None;
+            }
+        }
+    }
+
+    fn check_topup(&mut self) {
+        if !self.topup_pending {
+            return;
+        }
+
+        match topup::restore_if_complete(&self.base_path, self.backend.as_ref(), &self.state_path) {
+            Ok(Some(report)) => {
+                self.topup_pending = false;
+                self.status = Some(format!(
+                    "Top-up finished, thresholds restored to {}%-{}%",
+                    report.accepted.start, report.accepted.end
+                ));
+                self.applied = Some(report.accepted.clone());
+                self.thresholds = report.accepted;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                self.error = Some(format!(
+                    "Failed to restore thresholds after top-up: {}",
+                    err
+                ));
+            }
+        }
+    }
+
+    /// Writes back the thresholds batty last applied after firmware changed them.
+    fn reapply(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        if let Some(drift) = self.drift.take() {
+            self.thresholds = drift.expected;
+            self.save();
+        }
+    }
+
+    fn check_drift(&mut self) {
+        if self.on_devices_tab() {
+            return;
+        }
+
+        self.drift = State::load(&self.state_path)
+            .and_then(|state| drift::detect(&self.base_path, self.backend.as_ref(), &state))
+            .ok()
+            .flatten();
+    }
+
+    fn toggle_history(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        if self.history_view.is_some() {
+            self.history_view = None;
+        } else {
+            self.calibration_view = false;
+            self.load_history();
+        }
+    }
+
+    fn toggle_calibration(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        self.calibration_view = !self.calibration_view;
+        if self.calibration_view {
+            self.history_view = None;
+        }
+    }
+
+    fn start_calibration(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+        if self.calibration.is_some() {
+            self.error = Some("A calibration is already in progress".to_string());
+            self.status = None;
+            return;
+        }
+
+        match calibrate::start(&self.base_path, self.backend.as_ref(), &self.state_path) {
+            Ok(report) => {
+                self.calibration = calibrate::pending(&self.base_path, &self.state_path)
+                    .ok()
+                    .flatten();
+                self.calibration_result = None;
+                self.applied = Some(report.accepted.clone());
+                self.thresholds = report.accepted;
+                self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+                self.status = Some(format!(
+                    "Calibration started, thresholds raised to {}%-{}%",
+                    self.thresholds.start, self.thresholds.end
+                ));
+                self.error = None;
+            }
+            Err(err) => {
+                self.error = Some(format!("Failed to start calibration: {}", err));
+                self.status = None;
+            }
+        }
+    }
+
+    fn abort_calibration(&mut self) {
+        if self.refuse_on_devices_tab() {
+            return;
+        }
+
+        match calibrate::abort(&self.base_path, self.backend.as_ref(), &self.state_path) {
+            Ok(Some(report)) => {
+                self.calibration = None;
+                self.status = Some(format!(
+                    "Calibration cancelled, thresholds restored to {}%-{}%",
+                    report.accepted.start, report.accepted.end
+                ));
+                self.error = None;
+                self.applied = Some(report.accepted.clone());
+                self.thresholds = report.accepted;
+                self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+            }
+            Ok(None) => {
+                self.error = Some("No calibration in progress".to_string());
+                self.status = None;
+            }
+            Err(err) => {
+                self.error = Some(format!("Failed to cancel calibration: {}", err));
+                self.status = None;
+            }
+        }
+    }
+
+    fn check_calibration(&mut self) {
+        if self.calibration.is_none() || self.on_devices_tab() {
+            return;
+        }
+
+        match calibrate::advance(&self.base_path, self.backend.as_ref(), &self.state_path) {
+            Ok(Some(Progress::Waiting(_))) => {}
+            Ok(Some(Progress::Entered(phase))) => {
+                if let Some(calibration) = &mut self.calibration {
+                    calibration.phase = phase;
                 }
+                self.status = Some(format!("Calibration step {}/3: {}", phase.step(), phase));
+                self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+            }
+            Ok(Some(Progress::Finished(finished))) => {
+                self.calibration = None;
+                self.status = Some(format!(
+                    "Calibration finished, thresholds restored to {}%-{}%",
+                    finished.restored.accepted.start, finished.restored.accepted.end
+                ));
+                self.applied = Some(finished.restored.accepted.clone());
+                self.thresholds = finished.restored.accepted.clone();
+                self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+                self.calibration_result = Some(finished);
+            }
+            Ok(None) => self.calibration = None,
+            Err(err) => {
+                self.error = Some(format!("Failed to advance calibration: {}", err));
+            }
+        }
+    }
+
+    fn load_history(&mut self) {
+        let name = battery_name(&self.base_path);
+
+        match HistoryView::load(&self.history, name) {
+            Ok(view) => self.history_view = Some(view),
+            Err(err) => {
+                self.history_view = None;
+                self.error = Some(format!("Failed to read history: {}", err));
             }
         }
     }
 
+    fn record_power(&mut self) {
+        let Some(power) = self.battery.power_draw else {
+            return;
+        };
+        let now = Instant::now();
+
+        if self
+            .power_samples
+            .back()
+            .is_none_or(|(at, _)| now.duration_since(*at) >= POWER_SAMPLE_INTERVAL)
+        {
+            self.power_samples.push_back((now, power as u64 / 1000));
+        }
+        while self
+            .power_samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > POWER_WINDOW)
+        {
+            self.power_samples.pop_front();
+        }
+    }
+
+    /// Mean of the sampled power draw in watts.
+    fn average_power(&self) -> Option<f32> {
+        if self.power_samples.is_empty() {
+            return None;
+        }
+        let total: u64 = self.power_samples.iter().map(|(_, mw)| mw).sum();
+        Some(total as f32 / self.power_samples.len() as f32 / 1000.0)
+    }
+
+    fn load_backend(&mut self) {
+        self.backend = self.backends.for_battery(&self.base_path);
+        self.topup_pending = matches!(
+            topup::pending(&self.base_path, &self.state_path),
+            Ok(Some(_))
+        );
+        self.calibration = calibrate::pending(&self.base_path, &self.state_path)
+            .ok()
+            .flatten();
+        self.calibration_result = None;
+        self.applied = Thresholds::load(&self.base_path, self.backend.as_ref()).ok();
+        self.thresholds = self.applied.clone().unwrap_or_default();
+        self.selected_setting = Setting::Threshold(default_threshold_kind(
+            self.backend.capability(&self.base_path),
+        ));
+        self.charge_behaviour = ChargeBehaviour::load(&self.base_path).ok();
+        self.charge_behaviours = ChargeBehaviour::available(&self.base_path);
+        self.power_samples.clear();
+
+        if self.history_view.is_some() {
+            self.load_history();
+        }
+    }
+
+    /// Settings that can be edited: the thresholds the backend supports, then the charge
+    /// behaviour when the battery has one.
+    fn settings(&self) -> Vec<Setting> {
+        let mut settings = match self.backend.capability(&self.base_path) {
+            Capability::StartAndEnd => vec![
+                Setting::Threshold(ThresholdKind::Start),
+                Setting::Threshold(ThresholdKind::End),
+            ],
+            Capability::EndOnly | Capability::OnOff => vec![Setting::Threshold(ThresholdKind::End)],
+        };
+        if !self.charge_behaviours.is_empty() {
+            settings.push(Setting::ChargeBehaviour);
+        }
+        settings
+    }
+
+    fn select_setting(&mut self, delta: isize) {
+        let settings = self.settings();
+        let current = settings
+            .iter()
+            .position(|setting| *setting == self.selected_setting)
+            .unwrap_or(0) as isize;
+        self.selected_setting =
+            settings[(current + delta).rem_euclid(settings.len() as isize) as usize];
+    }
+
+    fn next_tab(&mut self) {
+        let tab_count = self.bat_paths.len() + usize::from(self.show_devices);
+        if self.selected_tab + 1 < tab_count {
+            self.selected_tab += 1;
+            self.load_selected_tab();
+        }
+    }
+
     fn prev_tab(&mut self) {
         if self.selected_tab > 0 {
             self.selected_tab -= 1;
-            self.base_path = self.bat_paths[self.selected_tab].clone();
-            self.thresholds = Thresholds::load(&self.base_path).unwrap_or_default();
-
-            match Battery::new(&self.base_path) {
-                Ok((battery, warnings)) => {
-                    self.battery = battery;
-                    self.warnings = warnings;
-                    self.status = None;
-                    self.error = None;
-                }
-                Err(e) => {
-                    self.error = Some(format!("Failed to load battery: {}", e));
-                    self.status = None;
-                    self.warnings.clear();
-                }
+            self.load_selected_tab();
+        }
+    }
+
+    /// Rereads the battery and power sources and advances pending operations. Called once
+    /// per refresh interval, so drawing never touches sysfs or the state file.
+    fn tick(&mut self) {
+        match self.battery.refresh() {
+            Ok(warnings) => {
+                self.warnings = warnings;
+            }
+            Err(e) => {
+                self.error = Some(format!("Failed to refresh battery data: {}", e));
+                self.warnings.clear();
             }
         }
+
+        if self.on_devices_tab() {
+            self.devices = device::find_devices(&self.power_supply_path);
+        }
+        self.record_power();
+        self.power_sources = power_source::find_power_sources(&self.power_supply_path);
+        self.check_topup();
+        self.check_calibration();
+        self.check_drift();
+
+        if self
+            .history_view
+            .as_ref()
+            .is_some_and(HistoryView::is_stale)
+        {
+            self.load_history();
+        }
     }
-}
 
-fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
-    match app.battery.refresh() {
-        Ok(warnings) => {
-            app.warnings = warnings;
+    /// Loads the battery of the selected tab. The Devices tab keeps the last battery
+    /// loaded so switching back is instant.
+    fn load_selected_tab(&mut self) {
+        self.status = None;
+        self.error = None;
+
+        if self.on_devices_tab() {
+            self.devices = device::find_devices(&self.power_supply_path);
+            return;
         }
-        Err(e) => {
-            app.error = Some(format!("Failed to refresh battery data: {}", e));
-            app.warnings.clear();
+
+        self.base_path = self.bat_paths[self.selected_tab].clone();
+        self.load_backend();
+
+        match Battery::new(&self.base_path) {
+            Ok((battery, warnings)) => {
+                self.battery = battery;
+                self.warnings = warnings;
+            }
+            Err(e) => {
+                self.error = Some(format!("Failed to load battery: {}", e));
+                self.warnings.clear();
+            }
         }
     }
+}
+
+/// Chart data for one battery, in coordinates relative to the time it was loaded.
+struct HistoryView {
+    /// (hours ago, charge %), negative x so the newest sample is on the right.
+    charge: Vec<(f64, f64)>,
+    end_threshold: Vec<(f64, f64)>,
+    /// (days ago, health %), one point per day.
+    health: Vec<(f64, f64)>,
+    /// Daily full capacity, offset so the sparkline shows the drift rather than the total.
+    energy_full: Vec<u64>,
+    energy_full_range: Option<(u32, u32)>,
+    loaded_at: Instant,
+}
+
+impl HistoryView {
+    fn load(history: &History, battery_name: &str) -> io::Result<Self> {
+        let now = crate::state::now();
+
+        let recent = history.query(
+            Some(battery_name),
+            now.saturating_sub(CHARGE_HISTORY_HOURS * HOUR),
+            now + 1,
+        )?;
+        let hours_ago = |timestamp: u64| -((now.saturating_sub(timestamp)) as f64 / HOUR as f64);
+
+        let charge = recent
+            .iter()
+            .map(|s| (hours_ago(s.timestamp), s.charge_percentage() as f64))
+            .collect();
+        let end_threshold = recent
+            .iter()
+            .filter_map(|s| Some((hours_ago(s.timestamp), s.thresholds.as_ref()?.end as f64)))
+            .collect();
 
-    let show_tabs = app.bat_paths.len() > 1;
-    let has_footer = !app.warnings.is_empty() || app.error.is_some() || app.status.is_some();
+        let long_term = history.query(
+            Some(battery_name),
+            now.saturating_sub(HEALTH_HISTORY_DAYS * DAY),
+            now + 1,
+        )?;
+        let days_ago = |timestamp: u64| -((now.saturating_sub(timestamp)) as f64 / DAY as f64);
+
+        let health = history::bucket_means(&long_term, DAY, |s| s.health_percentage())
+            .into_iter()
+            .map(|(day, health)| (days_ago(day), health as f64))
+            .collect();
+
+        let daily_full: Vec<u32> =
+            history::bucket_means(&long_term, DAY, |s| Some(s.energy_full as f32))
+                .into_iter()
+                .map(|(_, full)| full as u32)
+                .collect();
+        let floor = daily_full
+            .iter()
+            .min()
+            .map(|min| min - min / 20)
+            .unwrap_or(0);
+        let energy_full_range = daily_full
+            .first()
+            .zip(daily_full.last())
+            .map(|(a, b)| (*a, *b));
+
+        Ok(Self {
+            charge,
+            end_threshold,
+            health,
+            energy_full: daily_full.iter().map(|v| (v - floor) as u64).collect(),
+            energy_full_range,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    fn is_stale(&self) -> bool {
+        self.loaded_at.elapsed() >= HISTORY_RELOAD_INTERVAL
+    }
+}
+
+fn status_color(theme: &Theme, status: BatteryStatus) -> Color {
+    match status {
+        BatteryStatus::Charging => theme.ok.0,
+        BatteryStatus::Discharging => theme.warning.0,
+        BatteryStatus::NotCharging => theme.info.0,
+        BatteryStatus::Full => Color::Blue,
+        BatteryStatus::Unknown => Color::DarkGray,
+    }
+}
+
+fn charge_type_color(charge_type: ChargeType) -> Color {
+    match charge_type {
+        ChargeType::Fast => Color::Magenta,
+        ChargeType::Standard => Color::Reset,
+        ChargeType::Trickle => Color::Cyan,
+        ChargeType::Adaptive | ChargeType::Custom | ChargeType::LongLife => Color::Blue,
+        ChargeType::Bypass => Color::Yellow,
+    }
+}
+
+fn charge_behaviour_color(theme: &Theme, behaviour: ChargeBehaviour) -> Color {
+    match behaviour {
+        ChargeBehaviour::Auto => Color::Reset,
+        ChargeBehaviour::InhibitCharge | ChargeBehaviour::InhibitChargeAwake => theme.warning.0,
+        ChargeBehaviour::ForceDischarge => theme.error.0,
+    }
+}
+
+fn default_threshold_kind(capability: Capability) -> ThresholdKind {
+    match capability {
+        Capability::StartAndEnd => ThresholdKind::Start,
+        Capability::EndOnly | Capability::OnOff => ThresholdKind::End,
+    }
+}
+
+fn draw_ui(frame: &mut Frame<'_>, app: &App) {
+    let show_tabs = app.bat_paths.len() > 1 || app.show_devices;
+    let has_footer = !app.warnings.is_empty()
+        || app.error.is_some()
+        || app.status.is_some()
+        || app.drift.is_some();
 
     // Calculate footer height based on number of lines needed
     let footer_height = if has_footer {
@@ -217,6 +1271,9 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
         if app.status.is_some() {
             lines += 1;
         }
+        if app.drift.is_some() {
+            lines += 1;
+        }
         lines += app.warnings.len();
         (lines.min(3) + 2) as u16 // Add 2 for borders
     } else {
@@ -252,7 +1309,7 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
 
     // Render tabs at very top if multiple batteries
     if show_tabs {
-        let tab_titles: Vec<String> = app
+        let mut tab_titles: Vec<String> = app
             .bat_paths
             .iter()
             .map(|path| {
@@ -262,6 +1319,9 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
                     .to_string()
             })
             .collect();
+        if app.show_devices {
+            tab_titles.push("Devices".to_string());
+        }
 
         let tabs_widget = Tabs::new(tab_titles)
             .block(Block::default().borders(Borders::ALL).title("Batteries"))
@@ -269,7 +1329,7 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
             .style(Style::default())
             .highlight_style(
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(app.settings.theme.accent.0)
                     .add_modifier(Modifier::BOLD),
             );
 
@@ -283,6 +1343,75 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
         main_layout[0]
     };
 
+    if app.on_devices_tab() {
+        draw_devices(frame, app, battery_container_area);
+    } else {
+        draw_battery(frame, app, battery_container_area, show_tabs);
+    }
+
+    // Render footer with warnings, errors, and status messages
+    if has_footer {
+        let footer_area = if show_tabs {
+            main_layout[2]
+        } else {
+            main_layout[1]
+        };
+
+        let mut footer_lines = Vec::new();
+
+        if let Some(error) = &app.error {
+            footer_lines.push(Line::from(vec![Span::styled(
+                format!("Error: {}", error),
+                Style::default()
+                    .fg(app.settings.theme.error.0)
+                    .add_modifier(Modifier::BOLD),
+            )]));
+        }
+
+        if let Some(status) = &app.status {
+            footer_lines.push(Line::from(vec![Span::styled(
+                status.clone(),
+                Style::default().fg(app.settings.theme.ok.0),
+            )]));
+        }
+
+        if let Some(drift) = &app.drift {
+            footer_lines.push(Line::from(vec![Span::styled(
+                format!(
+                    "Changed outside batty: {}. Press {} to re-apply.",
+                    drift,
+                    app.settings.keys.label(Action::Reapply)
+                ),
+                Style::default()
+                    .fg(app.settings.theme.warning.0)
+                    .add_modifier(Modifier::BOLD),
+            )]));
+        }
+
+        for warning in &app.warnings {
+            footer_lines.push(Line::from(vec![Span::styled(
+                format!("Warning: {}", warning),
+                Style::default().fg(app.settings.theme.warning.0),
+            )]));
+        }
+
+        let footer_widget = Paragraph::new(footer_lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default()),
+        );
+
+        frame.render_widget(footer_widget, footer_area);
+    }
+
+    if let Some(selected) = app.profile_picker {
+        draw_profile_picker(frame, app, selected);
+    }
+}
+
+fn draw_battery(frame: &mut Frame<'_>, app: &App, area: Rect, show_tabs: bool) {
+    let keys = &app.settings.keys;
+
     // Get battery name for the container title
     let battery_name = app
         .base_path
@@ -297,13 +1426,17 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
         .title_alignment(Alignment::Center)
         .style(Style::default());
 
-    let inner_area = battery_block.inner(battery_container_area);
-    frame.render_widget(battery_block, battery_container_area);
+    let inner_area = battery_block.inner(area);
+    frame.render_widget(battery_block, area);
 
-    // Layout inside the battery container: stats header + configuration
+    // Layout inside the battery container: stats header + power + configuration
     let inner_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(8),
+            Constraint::Min(0),
+        ])
         .split(inner_area);
 
     // Header stats layout
@@ -319,24 +1452,72 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
         .split(inner_layout[0]);
 
     let bat_percent = format!("{:.2}%", app.battery.charge_percentage());
+    let end = app.applied.as_ref().map_or(100, |t| t.end);
+    let estimate = match (
+        app.battery.time_to_threshold(end),
+        app.battery.time_to_empty(),
+    ) {
+        (Some(time), _) => Some(format!("{} to {}%", format_duration(time), end)),
+        (None, Some(time)) => Some(format!("{} left", format_duration(time))),
+        (None, None) => None,
+    };
+    let mut percentage_block = Block::default()
+        .title("Charge")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL);
+    if let Some(estimate) = estimate {
+        percentage_block = percentage_block.title(
+            Title::from(estimate)
+                .position(Position::Bottom)
+                .alignment(Alignment::Center),
+        );
+    }
     let percentage_widget = Paragraph::new(bat_percent)
-        .block(
-            Block::default()
-                .title("Charge")
-                .title_alignment(Alignment::Center)
-                .borders(Borders::ALL),
-        )
+        .block(percentage_block)
         .centered();
 
-    let status = app.battery.status.as_str();
-    let status_widget = Paragraph::new(status)
-        .block(
-            Block::default()
-                .title("Status")
-                .title_alignment(Alignment::Center)
-                .borders(Borders::ALL),
-        )
-        .centered();
+    let status = Span::styled(
+        app.battery.status.as_str(),
+        Style::default()
+            .fg(status_color(&app.settings.theme, app.battery.status))
+            .add_modifier(Modifier::BOLD),
+    );
+
+    // Charge type and a non-default charge behaviour go on the bottom border to keep
+    // the status itself short enough for the box.
+    let mut charging = Vec::new();
+    if let Some(charge_type) = app.battery.charge_type {
+        charging.push(Span::styled(
+            charge_type.as_str(),
+            Style::default().fg(charge_type_color(charge_type)),
+        ));
+    }
+    if let Some(behaviour) = app
+        .battery
+        .charge_behaviour
+        .filter(|b| *b != ChargeBehaviour::Auto)
+    {
+        if !charging.is_empty() {
+            charging.push(Span::raw(" · "));
+        }
+        charging.push(Span::styled(
+            behaviour.as_str(),
+            Style::default().fg(charge_behaviour_color(&app.settings.theme, behaviour)),
+        ));
+    }
+
+    let mut status_block = Block::default()
+        .title("Status")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL);
+    if !charging.is_empty() {
+        status_block = status_block.title(
+            Title::from(Line::from(charging))
+                .position(Position::Bottom)
+                .alignment(Alignment::Center),
+        );
+    }
+    let status_widget = Paragraph::new(status).block(status_block).centered();
 
     let cycles = app
         .battery
@@ -371,77 +1552,478 @@ fn draw_ui(frame: &mut Frame<'_>, app: &mut App) {
     frame.render_widget(cycles_widget, header_layout[2]);
     frame.render_widget(health_widget, header_layout[3]);
 
-    let start_selected = app.curr_threshold_kind == ThresholdKind::Start;
-
     let mut lines = vec![
         Line::from(format_selected(
-            start_selected,
+            app.selected_setting == Setting::Threshold(ThresholdKind::Start),
             &format!("Start threshold: {}%", app.thresholds.start),
         )),
         Line::from(format_selected(
-            !start_selected,
+            app.selected_setting == Setting::Threshold(ThresholdKind::End),
             &format!("End threshold:   {}%", app.thresholds.end),
         )),
-        Line::from(""),
     ];
 
+    if let (Some(behaviour), false) = (app.charge_behaviour, app.charge_behaviours.is_empty()) {
+        lines.push(Line::from(Span::styled(
+            format_selected(
+                app.selected_setting == Setting::ChargeBehaviour,
+                &format!("Charge behaviour: {}", behaviour.as_str()),
+            ),
+            Style::default().fg(charge_behaviour_color(&app.settings.theme, behaviour)),
+        )));
+    }
+
+    if app.topup_pending {
+        lines.push(Line::from(Span::styled(
+            "  Top-up in progress: charging to 100% once",
+            Style::default().fg(app.settings.theme.info.0),
+        )));
+    }
+
+    if let Some(calibration) = &app.calibration {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  Calibration in progress: step {}/3, {}",
+                calibration.phase.step(),
+                calibration.phase
+            ),
+            Style::default().fg(app.settings.theme.info.0),
+        )));
+    }
+
+    lines.push(Line::from(""));
+
     if show_tabs {
-        lines.push(Line::from("• ←/→ or [/]: switch battery tabs"));
+        lines.push(Line::from(format!(
+            "• {}: switch battery tabs",
+            keys.pair_label(Action::PreviousTab, Action::NextTab)
+        )));
     }
 
     lines.extend_from_slice(&[
-        Line::from("• ↑/↓ or +/-: adjust thresholds"),
-        Line::from("• j/k: select threshold"),
-        Line::from("• Enter: save"),
-        Line::from("If saving fails, rerun with sudo or adjust udev permissions."),
+        Line::from(format!(
+            "• {}: adjust the selected setting",
+            keys.pair_label(Action::Increase, Action::Decrease)
+        )),
+        Line::from(format!(
+            "• {}: select threshold or charge behaviour",
+            keys.pair_label(Action::NextSetting, Action::PreviousSetting)
+        )),
+        Line::from(format!(
+            "• {}: load a profile",
+            keys.label(Action::Profiles)
+        )),
+        Line::from(format!(
+            "• {}: charge to 100% once (press again to cancel)",
+            keys.label(Action::Topup)
+        )),
+        Line::from(format!("• {}: show history", keys.label(Action::History))),
+        Line::from(format!(
+            "• {}: calibrate the battery gauge",
+            keys.label(Action::Calibrate)
+        )),
+        Line::from(format!(
+            "• {}: re-apply thresholds changed outside batty",
+            keys.label(Action::Reapply)
+        )),
+        Line::from(format!("• {}: save", keys.label(Action::Save))),
+        Line::from("If saving fails, rerun with sudo or run `batty setup-permissions`."),
     ]);
 
     let config_widget = Paragraph::new(lines).block(
         Block::default()
-            .title("Threshold Configuration")
+            .title(format!(
+                "Threshold Configuration ({}: {})",
+                app.backend.name(),
+                app.backend.capability(&app.base_path)
+            ))
             .borders(Borders::ALL),
     );
 
-    frame.render_widget(config_widget, inner_layout[1]);
+    draw_power(frame, app, inner_layout[1]);
 
-    // Render footer with warnings, errors, and status messages
-    if has_footer {
-        let footer_area = if show_tabs {
-            main_layout[2]
-        } else {
-            main_layout[1]
-        };
+    match &app.history_view {
+        Some(view) => draw_history(frame, view, keys, inner_layout[2]),
+        None if app.calibration_view => draw_calibration(frame, app, inner_layout[2]),
+        None => frame.render_widget(config_widget, inner_layout[2]),
+    }
+}
 
-        let mut footer_lines = Vec::new();
+fn draw_calibration(frame: &mut Frame<'_>, app: &App, area: Rect) {
+    let keys = &app.settings.keys;
+    let mut lines = vec![
+        Line::from("Charges to 100%, discharges fully and recharges so the gauge relearns the"),
+        Line::from("full capacity. Progress is saved and survives reboots."),
+        Line::from(""),
+    ];
 
-        if let Some(error) = &app.error {
-            footer_lines.push(Line::from(vec![Span::styled(
-                format!("Error: {}", error),
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-            )]));
-        }
+    let current = app.calibration.as_ref().map(|c| c.phase);
+    for phase in Phase::ALL {
+        let (marker, style) = match current {
+            Some(current) if current == phase => (
+                "▸",
+                Style::default()
+                    .fg(app.settings.theme.accent.0)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Some(current) if current.step() > phase.step() => {
+                ("✓", Style::default().fg(app.settings.theme.ok.0))
+            }
+            _ => (" ", Style::default()),
+        };
+        lines.push(Line::from(Span::styled(
+            format!("{} {}. {}", marker, phase.step(), phase),
+            style,
+        )));
+    }
+    lines.push(Line::from(""));
 
-        if let Some(status) = &app.status {
-            footer_lines.push(Line::from(vec![Span::styled(
-                status.clone(),
-                Style::default().fg(Color::Green),
-            )]));
+    if let Some(calibration) = &app.calibration {
+        let elapsed = Duration::from_secs(state::now().saturating_sub(calibration.started));
+        lines.extend_from_slice(&[
+            Line::from(Span::styled(
+                calibration
+                    .phase
+                    .instructions(calibrate::can_force_discharge(&app.base_path)),
+                Style::default().fg(app.settings.theme.info.0),
+            )),
+            Line::from(format!(
+                "Full capacity: {} at the start, {} now",
+                calibrate::format_capacity(calibration.capacity_before, app.battery.unit),
+                calibrate::format_capacity(app.battery.total_power, app.battery.unit)
+            )),
+            Line::from(format!("Running for {}", format_duration(elapsed))),
+            Line::from(""),
+            Line::from(format!(
+                "• {}: cancel and restore the thresholds",
+                keys.label(Action::CancelCalibration)
+            )),
+        ]);
+    } else {
+        if let Some(finished) = &app.calibration_result {
+            lines.push(Line::from(Span::styled(
+                format!("Finished. {}", finished.summary()),
+                Style::default().fg(app.settings.theme.ok.0),
+            )));
+            lines.push(Line::from(""));
         }
+        lines.push(Line::from(format!(
+            "Thresholds are raised to {}%-{}% and restored at the end.",
+            calibrate::CALIBRATION_THRESHOLDS.start,
+            calibrate::CALIBRATION_THRESHOLDS.end
+        )));
+        lines.push(Line::from(format!(
+            "• {}: start calibration",
+            keys.label(Action::Save)
+        )));
+    }
+    lines.push(Line::from(format!(
+        "• {}: back to thresholds",
+        keys.label(Action::Calibrate)
+    )));
 
-        for warning in &app.warnings {
-            footer_lines.push(Line::from(vec![Span::styled(
-                format!("Warning: {}", warning),
-                Style::default().fg(Color::Yellow),
-            )]));
-        }
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title(format!(
+                "Calibration ({}: back to thresholds)",
+                keys.label(Action::Calibrate)
+            ))
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(widget, area);
+}
 
-        let footer_widget = Paragraph::new(footer_lines).block(
+fn draw_devices(frame: &mut Frame<'_>, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Devices ")
+        .title_alignment(Alignment::Center);
+
+    if app.devices.is_empty() {
+        let message = Paragraph::new(vec![
+            Line::from("No peripheral batteries found."),
+            Line::from("Wireless mice, keyboards and headsets appear here while connected."),
+        ])
+        .block(block);
+        frame.render_widget(message, area);
+        return;
+    }
+
+    let header = Row::new(["Device", "Manufacturer", "Level", "Status"])
+        .style(Style::default().add_modifier(Modifier::BOLD))
+        .bottom_margin(1);
+    let rows = app.devices.iter().map(|device| {
+        Row::new([
+            Cell::from(device.display_name().to_string()),
+            Cell::from(
+                device
+                    .manufacturer
+                    .clone()
+                    .unwrap_or_else(|| "--".to_string()),
+            ),
+            Cell::from(device.level()),
+            Cell::from(Span::styled(
+                device.status.as_str(),
+                Style::default().fg(status_color(&app.settings.theme, device.status)),
+            )),
+        ])
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(40),
+            Constraint::Percentage(25),
+            Constraint::Percentage(15),
+            Constraint::Percentage(20),
+        ],
+    )
+    .header(header)
+    .block(block);
+    frame.render_widget(table, area);
+}
+
+fn draw_power(frame: &mut Frame<'_>, app: &App, area: Rect) {
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(48), Constraint::Min(0)])
+        .split(area);
+
+    let readings = &app.battery.readings;
+    let reading = |value: Option<f32>, unit: &str, precision: usize| {
+        value
+            .map(|v| format!("{:.*} {}", precision, v, unit))
+            .unwrap_or_else(|| "--".to_string())
+    };
+
+    let mut power = reading(readings.power, "W", 2);
+    if let Some(average) = app.average_power() {
+        power.push_str(&format!(" (avg {:.2} W)", average));
+    }
+    let mut voltage = reading(readings.voltage, "V", 2);
+    if let Some(min) = readings.voltage_min_design {
+        voltage.push_str(&format!(" (min {:.2} V)", min));
+    }
+
+    let online: Vec<String> = app
+        .power_sources
+        .iter()
+        .filter(|s| s.online)
+        .map(PowerSource::describe)
+        .collect();
+    let source = if online.is_empty() {
+        "battery".to_string()
+    } else {
+        online.join(", ")
+    };
+    let reason = match power_source::not_charging_reason(
+        &app.battery,
+        &app.power_sources,
+        app.applied.as_ref(),
+    ) {
+        Some(reason) => Span::styled(
+            format!("Not charging: {}", reason),
+            Style::default().fg(app.settings.theme.warning.0),
+        ),
+        None => Span::raw(""),
+    };
+
+    let power_widget = Paragraph::new(vec![
+        Line::from(format!("Power:   {}", power)),
+        Line::from(format!("Current: {}", reading(readings.current, "A", 2))),
+        Line::from(format!("Voltage: {}", voltage)),
+        Line::from(format!(
+            "Temp:    {}",
+            reading(readings.temperature, "°C", 1)
+        )),
+        Line::from(format!("Source:  {}", source)),
+        Line::from(reason),
+    ])
+    .block(Block::default().title("Power").borders(Borders::ALL));
+    frame.render_widget(power_widget, layout[0]);
+
+    // Only the newest samples that fit inside the borders are drawn.
+    let width = layout[1].width.saturating_sub(2) as usize;
+    let draw: Vec<u64> = app
+        .power_samples
+        .iter()
+        .skip(app.power_samples.len().saturating_sub(width))
+        .map(|(_, mw)| *mw)
+        .collect();
+    let sparkline = Sparkline::default()
+        .block(
             Block::default()
-                .borders(Borders::ALL)
-                .style(Style::default()),
-        );
+                .title(format!("Draw, last {} min", POWER_WINDOW.as_secs() / 60))
+                .borders(Borders::ALL),
+        )
+        .data(&draw)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(sparkline, layout[1]);
+}
 
-        frame.render_widget(footer_widget, footer_area);
+fn draw_history(frame: &mut Frame<'_>, view: &HistoryView, keys: &KeyBindings, area: Rect) {
+    if view.charge.is_empty() && view.health.is_empty() {
+        let message = Paragraph::new(vec![
+            Line::from("No history recorded for this battery yet."),
+            Line::from("Run `batty log` (as a service or in another terminal) to start recording."),
+            Line::from(""),
+            Line::from(format!(
+                "• {}: back to thresholds",
+                keys.label(Action::History)
+            )),
+        ])
+        .block(Block::default().title("History").borders(Borders::ALL));
+        frame.render_widget(message, area);
+        return;
+    }
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(50),
+            Constraint::Min(6),
+            Constraint::Length(5),
+        ])
+        .split(area);
+
+    let charge_chart = Chart::new(vec![
+        Dataset::default()
+            .name("charge")
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Green))
+            .data(&view.charge),
+        Dataset::default()
+            .name("end threshold")
+            .marker(Marker::Dot)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&view.end_threshold),
+    ])
+    .block(
+        Block::default()
+            .title(format!(
+                "Charge, last {}h ({}: back to thresholds)",
+                CHARGE_HISTORY_HOURS,
+                keys.label(Action::History)
+            ))
+            .borders(Borders::ALL),
+    )
+    .x_axis(
+        Axis::default()
+            .bounds([-(CHARGE_HISTORY_HOURS as f64), 0.0])
+            .labels(vec![
+                Span::raw(format!("-{}h", CHARGE_HISTORY_HOURS)),
+                Span::raw(format!("-{}h", CHARGE_HISTORY_HOURS / 2)),
+                Span::raw("now"),
+            ]),
+    )
+    .y_axis(Axis::default().bounds([0.0, 100.0]).labels(vec![
+        Span::raw("0%"),
+        Span::raw("50%"),
+        Span::raw("100%"),
+    ]));
+    frame.render_widget(charge_chart, layout[0]);
+
+    let (health_min, health_max) = view
+        .health
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), (_, h)| {
+            (lo.min(*h), hi.max(*h))
+        });
+    let (health_min, health_max) = if view.health.is_empty() {
+        (0.0, 100.0)
+    } else {
+        ((health_min - 2.0).floor(), (health_max + 2.0).ceil())
+    };
+
+    let health_chart = Chart::new(vec![Dataset::default()
+        .name("health")
+        .marker(Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Cyan))
+        .data(&view.health)])
+    .block(
+        Block::default()
+            .title(format!("Health, last {} days", HEALTH_HISTORY_DAYS))
+            .borders(Borders::ALL),
+    )
+    .x_axis(
+        Axis::default()
+            .bounds([-(HEALTH_HISTORY_DAYS as f64), 0.0])
+            .labels(vec![
+                Span::raw(format!("-{}d", HEALTH_HISTORY_DAYS)),
+                Span::raw(format!("-{}d", HEALTH_HISTORY_DAYS / 2)),
+                Span::raw("today"),
+            ]),
+    )
+    .y_axis(
+        Axis::default()
+            .bounds([health_min, health_max])
+            .labels(vec![
+                Span::raw(format!("{:.0}%", health_min)),
+                Span::raw(format!("{:.0}%", health_max)),
+            ]),
+    );
+    frame.render_widget(health_chart, layout[1]);
+
+    let title = match view.energy_full_range {
+        Some((first, last)) => format!(
+            "Full capacity per day: {:.1} Wh -> {:.1} Wh ({:+.1}%)",
+            first as f64 / 1_000_000.0,
+            last as f64 / 1_000_000.0,
+            (last as f64 / first as f64 - 1.0) * 100.0
+        ),
+        None => "Full capacity per day".to_string(),
+    };
+    let sparkline = Sparkline::default()
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .data(&view.energy_full)
+        .style(Style::default().fg(Color::Magenta));
+    frame.render_widget(sparkline, layout[2]);
+}
+
+fn draw_profile_picker(frame: &mut Frame<'_>, app: &App, selected: usize) {
+    let items: Vec<ListItem> = app
+        .profiles
+        .iter()
+        .map(|profile| {
+            ListItem::new(format!(
+                "{:<12} {}%-{}%",
+                profile.name, profile.thresholds.start, profile.thresholds.end
+            ))
+        })
+        .collect();
+
+    let height = (items.len() as u16 + 2).min(frame.size().height);
+    let area = centered_rect(36, height, frame.size());
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title("Profiles (Enter: load, Esc: close)")
+                .borders(Borders::ALL),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(app.settings.theme.accent.0)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("‣ ");
+
+    let mut state = ListState::default().with_selected(Some(selected));
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
     }
 }
 
@@ -0,0 +1,663 @@
+use crate::{
+    backend::Backends,
+    battery::{battery_name, Battery, BatteryStatus},
+    config::Problem,
+    drift::{self, Drift},
+    state::{self, State},
+    thresholds::Thresholds,
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, io,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+use zbus::{blocking::Connection, zvariant::Value};
+
+/// Charge is considered to have reached the end threshold within this many percent,
+/// since most firmware stops a little short of it.
+const THRESHOLD_TOLERANCE: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    /// A discharging battery dropped to the low level.
+    Low,
+    /// A discharging battery dropped to the critical level.
+    Critical,
+    /// A charging battery reached its end threshold.
+    ThresholdReached,
+    /// The thresholds no longer match the ones batty last applied, usually because
+    /// firmware reset them.
+    ThresholdReset,
+    /// Health dropped below the configured percentage.
+    Health,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Critical => write!(f, "critical"),
+            Self::ThresholdReached => write!(f, "threshold-reached"),
+            Self::ThresholdReset => write!(f, "threshold-reset"),
+            Self::Health => write!(f, "health"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SinkKind {
+    /// Freedesktop notifications over the D-Bus session bus.
+    Desktop,
+    /// The `hook` command, run through `sh -c`.
+    Hook,
+    /// Standard error, which ends up in the journal when run as a service.
+    Stderr,
+}
+
+/// The `[watch]` section of the config file.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Seconds between checks.
+    pub interval: u64,
+    pub low: u8,
+    pub critical: u8,
+    pub health_below: u8,
+    pub events: Vec<EventKind>,
+    pub sinks: Vec<SinkKind>,
+    /// Command run for every event by the hook sink. The event is passed in the
+    /// `BATTY_EVENT`, `BATTY_BATTERY`, `BATTY_CHARGE` and `BATTY_MESSAGE` variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+    /// Write the last applied thresholds back when firmware changes them.
+    pub reapply: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            low: 20,
+            critical: 5,
+            health_below: 80,
+            events: EventKind::value_variants().to_vec(),
+            sinks: vec![SinkKind::Desktop, SinkKind::Stderr],
+            hook: None,
+            reapply: false,
+        }
+    }
+}
+
+impl WatchConfig {
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        if self.interval == 0 {
+            problems.push(Problem::new(
+                &["watch.interval"],
+                "must be at least 1 second",
+            ));
+        }
+        for (key, value) in [
+            ("watch.low", self.low),
+            ("watch.critical", self.critical),
+            ("watch.health-below", self.health_below),
+        ] {
+            if value > 100 {
+                problems.push(Problem::new(&[key], "must be between 0 and 100"));
+            }
+        }
+        if self.critical > self.low {
+            problems.push(Problem::new(
+                &["watch.critical", "watch.low"],
+                format!("critical {}% is above low {}%", self.critical, self.low),
+            ));
+        }
+        if self.sinks.contains(&SinkKind::Hook) && self.hook.is_none() {
+            problems.push(Problem::new(
+                &["watch.sinks", "watch.hook"],
+                "the hook sink needs a hook command",
+            ));
+        }
+
+        problems
+    }
+}
+
+pub struct Event {
+    pub kind: EventKind,
+    pub battery: String,
+    pub charge: f32,
+    pub message: String,
+}
+
+impl Event {
+    fn summary(&self) -> String {
+        match self.kind {
+            EventKind::Low => format!("{} is low", self.battery),
+            EventKind::Critical => format!("{} is critically low", self.battery),
+            EventKind::ThresholdReached => format!("{} reached its end threshold", self.battery),
+            EventKind::ThresholdReset => format!("{} thresholds were reset", self.battery),
+            EventKind::Health => format!("{} health is degraded", self.battery),
+        }
+    }
+
+    /// Freedesktop urgency: 1 is normal, 2 is critical.
+    fn urgency(&self) -> u8 {
+        match self.kind {
+            EventKind::Critical => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// One monitored battery and the events currently active for it. An event fires when
+/// its condition starts to hold and again only after the condition has cleared.
+struct Watched {
+    path: PathBuf,
+    battery: Battery,
+    active: BTreeSet<EventKind>,
+}
+
+impl Watched {
+    fn check(
+        &mut self,
+        watch: &WatchConfig,
+        backends: &Backends,
+        drift: Option<&Drift>,
+    ) -> Vec<Event> {
+        let name = battery_name(&self.path).to_string();
+        let charge = self.battery.charge_percentage();
+        let discharging = self.battery.status == BatteryStatus::Discharging;
+        let thresholds =
+            Thresholds::load(&self.path, backends.for_battery(&self.path).as_ref()).ok();
+
+        let mut conditions: Vec<(EventKind, String)> = Vec::new();
+
+        if discharging && charge <= watch.critical as f32 {
+            conditions.push((EventKind::Critical, format!("Charge is {:.0}%", charge)));
+        } else if discharging && charge <= watch.low as f32 {
+            conditions.push((EventKind::Low, format!("Charge is {:.0}%", charge)));
+        }
+
+        if let Some(t) = &thresholds {
+            if !discharging && charge >= t.end as f32 - THRESHOLD_TOLERANCE {
+                conditions.push((
+                    EventKind::ThresholdReached,
+                    format!("Charge is {:.0}%, end threshold {}%", charge, t.end),
+                ));
+            }
+        }
+
+        if let Some(drift) = drift {
+            let mut message = drift.to_string();
+            message[..1].make_ascii_uppercase();
+            conditions.push((EventKind::ThresholdReset, message));
+        }
+
+        if let Some(health) = self.battery.health_percentage() {
+            if health < watch.health_below as f32 {
+                conditions.push((
+                    EventKind::Health,
+                    format!("Health is {:.1}%, below {}%", health, watch.health_below),
+                ));
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut active = BTreeSet::new();
+
+        for (kind, message) in conditions {
+            if !watch.events.contains(&kind) {
+                continue;
+            }
+            if !self.active.contains(&kind) {
+                events.push(Event {
+                    kind,
+                    battery: name.clone(),
+                    charge,
+                    message,
+                });
+            }
+            active.insert(kind);
+        }
+
+        self.active = active;
+        events
+    }
+}
+
+/// Delivers events to the configured sinks. The session bus connection is opened on
+/// first use and reopened after a failure, so a notification daemon that starts after
+/// batty is still picked up.
+struct Sinks<'a> {
+    watch: &'a WatchConfig,
+    bus: Option<Connection>,
+}
+
+impl Sinks<'_> {
+    fn deliver(&mut self, event: &Event) {
+        for sink in &self.watch.sinks {
+            let result = match sink {
+                SinkKind::Desktop => self.notify_desktop(event),
+                SinkKind::Hook => self.run_hook(event),
+                SinkKind::Stderr => {
+                    eprintln!("[{}] {}: {}", event.kind, event.battery, event.message);
+                    Ok(())
+                }
+            };
+
+            if let Err(e) = result {
+                eprintln!("Failed to deliver {} event: {}", event.kind, e);
+            }
+        }
+    }
+
+    fn notify_desktop(&mut self, event: &Event) -> Result<(), String> {
+        if self.bus.is_none() {
+            self.bus = Some(Connection::session().map_err(|e| e.to_string())?);
+        }
+        let Some(bus) = &self.bus else {
+            return Ok(());
+        };
+
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        hints.insert("urgency", Value::U8(event.urgency()));
+
+        let result = bus.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "batty",
+                0u32,
+                "battery",
+                event.summary(),
+                &event.message,
+                Vec::<&str>::new(),
+                hints,
+                -1i32,
+            ),
+        );
+
+        if let Err(e) = result {
+            self.bus = None;
+            return Err(e.to_string());
+        }
+        Ok(())
+    }
+
+    fn run_hook(&self, event: &Event) -> Result<(), String> {
+        let Some(hook) = &self.watch.hook else {
+            return Err("no hook command configured".to_string());
+        };
+
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("BATTY_EVENT", event.kind.to_string())
+            .env("BATTY_BATTERY", &event.battery)
+            .env("BATTY_CHARGE", format!("{:.0}", event.charge))
+            .env("BATTY_MESSAGE", &event.message)
+            .status()
+            .map_err(|e| e.to_string())?;
+
+        if !status.success() {
+            return Err(format!("hook exited with {}", status));
+        }
+        Ok(())
+    }
+}
+
+/// Checks `bat_paths` every `watch.interval` seconds, or once when `once` is set.
+/// Thresholds are compared with the ones batty last applied and, with `reapply`,
+/// written back when they differ.
+pub fn run(
+    watch: &WatchConfig,
+    bat_paths: &[PathBuf],
+    backends: &Backends,
+    state_path: &Path,
+    once: bool,
+) -> io::Result<()> {
+    if watch.sinks.contains(&SinkKind::Hook) && watch.hook.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the hook sink needs a hook command (--hook or `hook` in [watch])",
+        ));
+    }
+
+    let mut watched = Vec::new();
+    for path in bat_paths {
+        match Battery::new(path) {
+            Ok((battery, _)) => watched.push(Watched {
+                path: path.clone(),
+                battery,
+                active: BTreeSet::new(),
+            }),
+            Err(e) => eprintln!(
+                "Failed to read {}, not watching it: {}",
+                battery_name(path),
+                e
+            ),
+        }
+    }
+
+    if watched.is_empty() && !bat_paths.is_empty() {
+        return Err(io::Error::other("none of the batteries could be read"));
+    }
+
+    let mut sinks = Sinks { watch, bus: None };
+
+    loop {
+        let state = State::load(state_path)?;
+
+        for entry in &mut watched {
+            let name = battery_name(&entry.path).to_string();
+            if let Err(e) = entry.battery.refresh() {
+                eprintln!("Failed to read {}: {}", name, e);
+                continue;
+            }
+
+            let backend = backends.for_battery(&entry.path);
+            let drift = drift::detect(&entry.path, backend.as_ref(), &state)
+                .ok()
+                .flatten();
+
+            for event in entry.check(watch, backends, drift.as_ref()) {
+                sinks.deliver(&event);
+            }
+
+            if let (Some(drift), true) = (drift, watch.reapply) {
+                match drift.expected.save(&entry.path, backend.as_ref()) {
+                    Ok(report) => {
+                        eprintln!(
+                            "{}: thresholds re-applied to {}%-{}%",
+                            name, report.accepted.start, report.accepted.end
+                        );
+                        if let Err(e) = state::record_applied(state_path, &name, &report.accepted) {
+                            eprintln!("Failed to record applied thresholds: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to re-apply thresholds to {}: {}", name, e),
+                }
+            }
+        }
+
+        if once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(watch.interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::tests::PrivateBus;
+    use std::{fs, sync::mpsc};
+    use zbus::{
+        blocking::{connection, MessageIterator},
+        message::Type,
+        zvariant::OwnedValue,
+    };
+
+    struct Fixture {
+        root: tempfile::TempDir,
+        path: PathBuf,
+        backends: Backends,
+        watch: WatchConfig,
+    }
+
+    impl Fixture {
+        /// A power_supply tree with one battery of 100 Wh, 90 Wh full and 40-80% thresholds.
+        fn new() -> Self {
+            let root = tempfile::tempdir().unwrap();
+            let power_supply = root.path().join("class/power_supply");
+            let path = power_supply.join("BAT0");
+            fs::create_dir_all(&path).unwrap();
+
+            for (attr, value) in [
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("energy_now", "45000000"),
+                ("energy_full", "90000000"),
+                ("energy_full_design", "100000000"),
+                ("charge_control_start_threshold", "40"),
+                ("charge_control_end_threshold", "80"),
+            ] {
+                fs::write(path.join(attr), value).unwrap();
+            }
+
+            Self {
+                root,
+                backends: Backends::new(&power_supply, None),
+                path,
+                watch: WatchConfig::default(),
+            }
+        }
+
+        fn set(&self, attr: &str, value: &str) {
+            fs::write(self.path.join(attr), value).unwrap();
+        }
+
+        /// Sets the charge as a percentage of `energy_full`.
+        fn charge(&self, percent: u32) {
+            self.set("energy_now", &(percent * 900_000).to_string());
+        }
+
+        fn watched(&self) -> Watched {
+            Watched {
+                path: self.path.clone(),
+                battery: Battery::new(&self.path).unwrap().0,
+                active: BTreeSet::new(),
+            }
+        }
+
+        fn check(&self, watched: &mut Watched, drift: Option<&Drift>) -> Vec<EventKind> {
+            watched.battery.refresh().unwrap();
+            watched
+                .check(&self.watch, &self.backends, drift)
+                .into_iter()
+                .map(|event| event.kind)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn fires_low_then_critical() {
+        let fixture = Fixture::new();
+        let mut watched = fixture.watched();
+
+        assert!(fixture.check(&mut watched, None).is_empty());
+
+        fixture.charge(20);
+        assert_eq!(fixture.check(&mut watched, None), [EventKind::Low]);
+
+        fixture.charge(5);
+        assert_eq!(fixture.check(&mut watched, None), [EventKind::Critical]);
+    }
+
+    #[test]
+    fn fires_again_only_after_condition_clears() {
+        let fixture = Fixture::new();
+        let mut watched = fixture.watched();
+
+        fixture.charge(15);
+        assert_eq!(fixture.check(&mut watched, None), [EventKind::Low]);
+        fixture.charge(14);
+        assert!(fixture.check(&mut watched, None).is_empty());
+
+        fixture.set("status", "Charging");
+        assert!(fixture.check(&mut watched, None).is_empty());
+
+        fixture.set("status", "Discharging");
+        assert_eq!(fixture.check(&mut watched, None), [EventKind::Low]);
+    }
+
+    #[test]
+    fn threshold_reached_within_tolerance() {
+        let fixture = Fixture::new();
+        let mut watched = fixture.watched();
+        fixture.set("status", "Charging");
+
+        fixture.charge(78);
+        assert!(fixture.check(&mut watched, None).is_empty());
+
+        fixture.charge(79);
+        assert_eq!(
+            fixture.check(&mut watched, None),
+            [EventKind::ThresholdReached]
+        );
+
+        fixture.set("status", "Not charging");
+        fixture.charge(80);
+        assert!(fixture.check(&mut watched, None).is_empty());
+    }
+
+    #[test]
+    fn reports_drift_once() {
+        let fixture = Fixture::new();
+        let mut watched = fixture.watched();
+
+        let mut state = State::default();
+        state
+            .applied
+            .insert("BAT0".to_string(), Thresholds { start: 50, end: 60 });
+        let backend = fixture.backends.for_battery(&fixture.path);
+        let drift = drift::detect(&fixture.path, backend.as_ref(), &state)
+            .unwrap()
+            .unwrap();
+
+        let events = watched.check(&fixture.watch, &fixture.backends, Some(&drift));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::ThresholdReset);
+        assert_eq!(
+            events[0].message,
+            "Thresholds are 40%-80%, batty applied 50%-60%"
+        );
+
+        assert!(fixture.check(&mut watched, Some(&drift)).is_empty());
+        assert!(fixture.check(&mut watched, None).is_empty());
+        assert_eq!(
+            fixture.check(&mut watched, Some(&drift)),
+            [EventKind::ThresholdReset]
+        );
+    }
+
+    #[test]
+    fn reports_degraded_health_unless_disabled() {
+        let mut fixture = Fixture::new();
+        fixture.set("energy_full", "70000000");
+        let mut watched = fixture.watched();
+
+        assert_eq!(fixture.check(&mut watched, None), [EventKind::Health]);
+
+        fixture
+            .watch
+            .events
+            .retain(|kind| *kind != EventKind::Health);
+        let mut watched = fixture.watched();
+        assert!(fixture.check(&mut watched, None).is_empty());
+    }
+
+    /// Drains the battery to 15% and returns the resulting low event.
+    fn low_event(fixture: &Fixture) -> Event {
+        let mut watched = fixture.watched();
+        fixture.charge(15);
+        watched.battery.refresh().unwrap();
+        let mut events = watched.check(&fixture.watch, &fixture.backends, None);
+        assert_eq!(events.len(), 1);
+        events.remove(0)
+    }
+
+    #[test]
+    fn notifies_the_desktop_over_dbus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        let connect = || {
+            connection::Builder::address(bus.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+
+        // Stands in for the notification daemon and reports every Notify call
+        let server = connect();
+        server
+            .request_name("org.freedesktop.Notifications")
+            .unwrap();
+        let messages = MessageIterator::from(&server);
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            for message in messages.flatten() {
+                let header = message.header();
+                if header.message_type() != Type::MethodCall
+                    || header.member().map(|m| m.as_str()) != Some("Notify")
+                {
+                    continue;
+                }
+                type Notify = (
+                    String,
+                    u32,
+                    String,
+                    String,
+                    String,
+                    Vec<String>,
+                    HashMap<String, OwnedValue>,
+                    i32,
+                );
+                let (_, _, _, summary, body, _, hints, _): Notify =
+                    message.body().deserialize().unwrap();
+                let urgency = u8::try_from(&hints["urgency"]).unwrap();
+                server.reply(&header, &(1u32,)).unwrap();
+                sender.send((summary, body, urgency)).unwrap();
+            }
+        });
+
+        let mut fixture = Fixture::new();
+        fixture.watch.sinks = vec![SinkKind::Desktop];
+        let event = low_event(&fixture);
+        let mut sinks = Sinks {
+            watch: &fixture.watch,
+            bus: Some(connect()),
+        };
+        sinks.deliver(&event);
+
+        assert_eq!(
+            received.recv_timeout(Duration::from_secs(10)).unwrap(),
+            ("BAT0 is low".to_string(), "Charge is 15%".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn runs_the_hook_with_the_event() {
+        let mut fixture = Fixture::new();
+        let output = fixture.root.path().join("hook.out");
+        fixture.watch.sinks = vec![SinkKind::Hook];
+        fixture.watch.hook = Some(format!(
+            "printf '%s|%s|%s|%s' \"$BATTY_EVENT\" \"$BATTY_BATTERY\" \"$BATTY_CHARGE\" \"$BATTY_MESSAGE\" > '{}'",
+            output.display()
+        ));
+
+        let event = low_event(&fixture);
+        let mut sinks = Sinks {
+            watch: &fixture.watch,
+            bus: None,
+        };
+        sinks.deliver(&event);
+
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "low|BAT0|15|Charge is 15%"
+        );
+    }
+}